@@ -115,6 +115,7 @@ async fn likes() {
         .map(|history| LikesJson {
             video_id: history.videoId.clone().to_string(),
             user_id: history.userId.clone().to_string(),
+            timestamp: history.createdAt.map(|date| date.timestamp()),
         })
         .collect::<Vec<LikesJson>>();
 
@@ -142,6 +143,7 @@ async fn history() {
             watch_percentage: history.watchedPercent as f32,
             is_watched: history.isWatched,
             video_id: history.videoId.clone().to_string(),
+            timestamp: history.updatedAt.map(|date| date.timestamp()),
         })
         .collect::<Vec<HistoryJson>>();
 
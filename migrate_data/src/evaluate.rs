@@ -0,0 +1,210 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fs::File,
+    io::Read,
+};
+
+use serde::de::DeserializeOwned;
+
+use crate::{
+    models::{HistoryJson, LikesJson, VideosJson},
+    tokenize::{compute_cosine_similarity, compute_tf_matrix},
+};
+
+// Number of recommendations considered for each user
+const K: usize = 10;
+// Default weights of the `recommend` configuration (`LIKE_WEIGHT`, `WATCH_WEIGHT`, `REPEAT_VIEW_WEIGHT`)
+const LIKE_WEIGHT: f64 = 1.0;
+const WATCH_WEIGHT: f64 = 1.0;
+const REPEAT_VIEW_WEIGHT: f64 = 0.25;
+
+fn read_json<T: DeserializeOwned>(path: &str) -> Vec<T> {
+    let mut file = File::open(path).expect("Failed to open file");
+    let mut buff = String::new();
+    file.read_to_string(&mut buff).expect("Failed to read file");
+    serde_json::from_str(&buff).expect("Failed to deserialize json")
+}
+
+fn get_positives(likes: &[LikesJson], history: &[HistoryJson]) -> HashMap<String, Vec<String>> {
+    // Videos liked or watched by each user, with the same 0.7 rule as `store::history`,
+    // oldest first when the dump has timestamps
+    let mut interactions: Vec<(&String, &String, Option<i64>)> = likes
+        .iter()
+        .map(|like| (&like.user_id, &like.video_id, like.timestamp))
+        .chain(
+            history
+                .iter()
+                .filter(|view| view.is_watched || view.watch_percentage >= 0.7)
+                .map(|view| (&view.user_id, &view.video_id, view.timestamp)),
+        )
+        .collect();
+    interactions.sort_by_key(|(_, _, timestamp)| *timestamp);
+
+    let mut positives: HashMap<String, Vec<String>> = HashMap::new();
+    for (user_id, video_id, _) in interactions {
+        let videos = positives.entry(user_id.clone()).or_default();
+        if !videos.contains(video_id) {
+            videos.push(video_id.clone());
+        }
+    }
+    positives
+}
+
+fn get_weights(likes: &[LikesJson], history: &[HistoryJson]) -> HashMap<(String, String), f64> {
+    // Weight of each video of each user, like `history_weights` in `recommend`: a like, plus the best
+    // completed view scaled by its watched percentage and a bonus for each repeat view.
+    // The dump being a single point in time, interactions are not decayed by their age.
+    let mut weights: HashMap<(String, String), f64> = HashMap::new();
+    for like in likes {
+        weights.insert((like.user_id.clone(), like.video_id.clone()), LIKE_WEIGHT);
+    }
+    let mut views: HashMap<(String, String), Vec<f64>> = HashMap::new();
+    for view in history
+        .iter()
+        .filter(|view| view.is_watched || view.watch_percentage >= 0.7)
+    {
+        let percentage = (view.watch_percentage as f64).clamp(0.0, 1.0);
+        views
+            .entry((view.user_id.clone(), view.video_id.clone()))
+            .or_default()
+            .push(WATCH_WEIGHT * percentage);
+    }
+    for (key, mut watches) in views {
+        watches.sort_by(|a, b| b.total_cmp(a));
+        let repeat_views = (watches.len() - 1) as f64 * REPEAT_VIEW_WEIGHT;
+        *weights.entry(key).or_insert(0.0) += watches[0] + repeat_views;
+    }
+    weights
+}
+
+struct Metrics {
+    hits: usize,
+    reciprocal_rank: f64,
+    users: usize,
+}
+
+impl Metrics {
+    fn new() -> Metrics {
+        Metrics {
+            hits: 0,
+            reciprocal_rank: 0.0,
+            users: 0,
+        }
+    }
+
+    fn add(&mut self, ranking: &[String], held_out: &str) {
+        self.users += 1;
+        if let Some(rank) = ranking
+            .iter()
+            .take(K)
+            .position(|video_id| video_id == held_out)
+        {
+            self.hits += 1;
+            self.reciprocal_rank += 1.0 / (rank + 1) as f64;
+        }
+    }
+
+    fn print(&self, name: &str) {
+        let users = self.users.max(1) as f64;
+        println!(
+            "{:<12} HitRate@{K}: {:.3}  Precision@{K}: {:.3}  MRR@{K}: {:.3}",
+            name,
+            self.hits as f64 / users,
+            self.hits as f64 / (users * K as f64),
+            self.reciprocal_rank / users,
+        );
+    }
+}
+
+fn rank(scores: &HashMap<&str, f64>, seen: &HashSet<&str>) -> Vec<String> {
+    // Sort the unseen videos by score, ties are broken by id so the ranking is deterministic
+    let mut ranking: Vec<(&str, f64)> = scores
+        .iter()
+        .filter(|(video_id, _)| !seen.contains(*video_id))
+        .map(|(video_id, score)| (*video_id, *score))
+        .collect();
+    ranking.sort_by(|a, b| b.1.total_cmp(&a.1).then_with(|| a.0.cmp(b.0)));
+    ranking
+        .into_iter()
+        .map(|(video_id, _)| video_id.to_string())
+        .collect()
+}
+
+pub fn main() {
+    let videos: Vec<VideosJson> = read_json("../videos.json");
+    let likes: Vec<LikesJson> = read_json("../likes.json");
+    let history: Vec<HistoryJson> = read_json("../history.json");
+
+    let (tf_matrix, _) = compute_tf_matrix(&videos);
+    let cosine_sim = compute_cosine_similarity(&tf_matrix);
+    let index: HashMap<&str, usize> = videos
+        .iter()
+        .enumerate()
+        .map(|(i, video)| (video.video_id.as_str(), i))
+        .collect();
+
+    // Leave one out: the most recent video of each user is hidden and has to be found back
+    let positives = get_positives(&likes, &history);
+    let weights = get_weights(&likes, &history);
+    let splits: Vec<(&String, &[String], &String)> = positives
+        .iter()
+        .filter(|(_, videos)| videos.len() >= 2)
+        .map(|(user_id, videos)| {
+            let (held_out, train) = videos.split_last().unwrap();
+            (user_id, train, held_out)
+        })
+        .collect();
+
+    // The popularity baseline only knows about the training interactions
+    let mut popularity: HashMap<&str, f64> = videos
+        .iter()
+        .map(|video| (video.video_id.as_str(), 0.0))
+        .collect();
+    for videos in positives.values() {
+        let train = if videos.len() >= 2 {
+            &videos[..videos.len() - 1]
+        } else {
+            &videos[..]
+        };
+        for video_id in train {
+            if let Some(score) = popularity.get_mut(video_id.as_str()) {
+                *score += 1.0;
+            }
+        }
+    }
+
+    let mut popularity_metrics = Metrics::new();
+    let mut content_metrics = Metrics::new();
+    for (user_id, train, held_out) in splits.iter() {
+        let seen: HashSet<&str> = train.iter().map(|video_id| video_id.as_str()).collect();
+
+        popularity_metrics.add(&rank(&popularity, &seen), held_out);
+
+        // The `content` strategy of `recommend` on its own, with its default configuration: mean
+        // similarity to the videos already seen, weighted by engagement. The collaborative and
+        // popularity parts of the blend, the decay and the re-rankings are not reproduced here.
+        let train_weights: Vec<(usize, f64)> = train
+            .iter()
+            .filter_map(|video_id| {
+                let weight = weights.get(&((*user_id).clone(), video_id.clone()))?;
+                Some((*index.get(video_id.as_str())?, *weight))
+            })
+            .collect();
+        let total_weight: f64 = train_weights.iter().map(|(_, weight)| weight).sum();
+        let content: HashMap<&str, f64> = index
+            .iter()
+            .map(|(video_id, &i)| {
+                let total: f64 = train_weights
+                    .iter()
+                    .map(|&(j, weight)| weight * cosine_sim[(i, j)])
+                    .sum();
+                (*video_id, total / total_weight.max(f64::MIN_POSITIVE))
+            })
+            .collect();
+        content_metrics.add(&rank(&content, &seen), held_out);
+    }
+
+    println!("Evaluated users: {}", splits.len());
+    popularity_metrics.print("popularity");
+    content_metrics.print("content");
+}
@@ -1,4 +1,5 @@
 mod dump;
+mod evaluate;
 mod store;
 mod models;
 mod tokenize;
@@ -20,6 +21,8 @@ enum Mode {
     Store,
     /// Tokenize data
     Tokenize,
+    /// Evaluate recommendations offline against the popularity baseline
+    Evaluate,
 }
 
 
@@ -38,6 +41,10 @@ async fn main() {
             println!("Tokenizing data");
             tokenize::main();
         }
+        Mode::Evaluate => {
+            println!("Evaluating recommendations");
+            evaluate::main();
+        }
     }
     println!("Done");
 }
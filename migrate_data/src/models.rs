@@ -1,4 +1,5 @@
 use serde::{Serialize, Deserialize};
+use sqlx::types::chrono::NaiveDateTime;
 use uuid::Uuid;
 
 #[derive(Debug, sqlx::FromRow, Clone)]
@@ -31,12 +32,17 @@ pub struct VideosJson {
 pub struct Like {
     pub userId: String,
     pub videoId: Uuid,
+    #[sqlx(default)]
+    pub createdAt: Option<NaiveDateTime>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct LikesJson {
     pub video_id: String,
     pub user_id: String,
+    // Unix timestamp in seconds of the like, missing in dumps made before it was collected
+    #[serde(default)]
+    pub timestamp: Option<i64>,
 }
 
 #[derive(Debug, sqlx::FromRow, Clone)]
@@ -46,6 +52,8 @@ pub struct History {
     pub watchedPercent: f64,
     pub isWatched: bool,
     pub videoId: Uuid,
+    #[sqlx(default)]
+    pub updatedAt: Option<NaiveDateTime>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -55,4 +63,7 @@ pub struct HistoryJson {
     pub watch_percentage: f32,
     pub is_watched: bool,
     pub video_id: String,
+    // Unix timestamp in seconds of the last watch, missing in dumps made before it was collected
+    #[serde(default)]
+    pub timestamp: Option<i64>,
 }
\ No newline at end of file
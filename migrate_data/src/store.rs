@@ -1,4 +1,4 @@
-use std::{fs::File, io::Read};
+use std::{collections::HashMap, fs::File, io::Read};
 
 use gremlin_client::{
     process::traversal::{traversal, GraphTraversalSource, SyncTerminator},
@@ -34,15 +34,33 @@ fn videos(ref g: &GraphTraversalSource<SyncTerminator>) {
     file.read_to_string(&mut buff).expect("Failed to read file");
     let videos: Vec<VideosJson> = serde_json::from_str(&buff).expect("Failed to deserialize json");
 
-    // Store data in database
+    // Store data in database, each publisher once with a `published` edge to each of its videos.
+    // Videos keep their publisher_id so that they can be read without following the edge.
+    let mut publishers = HashMap::new();
     for video in videos {
-        g.add_v("video")
+        let publisher = publishers
+            .entry(video.publisher_id.clone())
+            .or_insert_with(|| {
+                g.add_v("publisher")
+                    .property("publisher_id", video.publisher_id.clone())
+                    .next()
+                    .expect("Failed to add publisher to database")
+                    .expect("Publisher not added")
+            });
+        let vertex = g
+            .add_v("video")
             .property("video_id", video.video_id)
             .property("title", video.title)
             .property("description", video.description)
             .property("publisher_id", video.publisher_id)
             .next()
-            .expect("Failed to add video to database");
+            .expect("Failed to add video to database")
+            .expect("Video not added");
+        g.add_e("published")
+            .from(&*publisher)
+            .to(&vertex)
+            .next()
+            .expect("Failed to add published edge to database");
     }
 }
 
@@ -100,11 +118,11 @@ fn likes(ref g: &GraphTraversalSource<SyncTerminator>) {
             .has(("video_id", like.video_id.clone()))
             .next();
         if let (Ok(Some(user)), Ok(Some(video))) = (user, video) {
-            g.add_e("likes")
-                .from(&user)
-                .to(&video)
-                .next()
-                .expect("Failed to add like to database");
+            let mut edge = g.add_e("likes").from(&user).to(&video);
+            if let Some(timestamp) = like.timestamp {
+                edge = edge.property("timestamp", timestamp);
+            }
+            edge.next().expect("Failed to add like to database");
         } else {
             println!("User with id {} or video with id {} not found", like.user_id, like.video_id);
         }
@@ -123,38 +141,53 @@ fn history(ref g: &GraphTraversalSource<SyncTerminator>) {
 
     // Store data in database
     let mut count = 0;
+    let mut in_progress_count = 0;
     for view in history {
-        // We consider that a video is viewed if it is in the history and isWatched is true or if watchedPercentage is greater than 0.7
-        if view.is_watched || view.watch_percentage >= 0.7 {
+        // We consider that a video is viewed if it is in the history and isWatched is true or if watchedPercentage is greater than 0.7,
+        // videos started but not finished are kept as `in_progress` so that they can be resumed
+        let label = if view.is_watched || view.watch_percentage >= 0.7 {
             count += 1;
-            // Get user
-            let user = g
-                .v(())
-                .has_label("user")
-                .has(("user_id", view.user_id.clone()))
-                .next()
-                .expect("Failed to get user");
-            // Get video
-            let video = g
-                .v(())
-                .has_label("video")
-                .has(("video_id", view.video_id.clone()))
-                .next()
-                .expect("Failed to get video");
-            // Add view if user and video exist
-            if user.is_some() && video.is_some() {
-                g.add_e("watched")
-                    .from(&user.unwrap())
-                    .to(&video.unwrap())
-                    .property("watchedPercentage", view.watch_percentage)
-                    .next()
-                    .expect("Failed to add view to database");
-            } else {
-                println!("User or video not found for view: {:?}", view);
+            "watched"
+        } else if view.watch_time > 0.0 || view.watch_percentage > 0.0 {
+            in_progress_count += 1;
+            "in_progress"
+        } else {
+            continue;
+        };
+        // Get user
+        let user = g
+            .v(())
+            .has_label("user")
+            .has(("user_id", view.user_id.clone()))
+            .next()
+            .expect("Failed to get user");
+        // Get video
+        let video = g
+            .v(())
+            .has_label("video")
+            .has(("video_id", view.video_id.clone()))
+            .next()
+            .expect("Failed to get video");
+        // Add view if user and video exist
+        if user.is_some() && video.is_some() {
+            let mut edge = g
+                .add_e(label)
+                .from(&user.unwrap())
+                .to(&video.unwrap())
+                .property("watchedPercentage", view.watch_percentage);
+            if label == "in_progress" {
+                edge = edge.property("watchTime", view.watch_time);
             }
+            if let Some(timestamp) = view.timestamp {
+                edge = edge.property("timestamp", timestamp);
+            }
+            edge.next().expect("Failed to add view to database");
+        } else {
+            println!("User or video not found for view: {:?}", view);
         }
     }
     println!("Count: {}", count);
+    println!("In progress: {}", in_progress_count);
 }
 
 fn verify_db_is_running() -> bool {
@@ -228,6 +261,15 @@ pub fn store() {
             .expect("Failed to count videos")
             .expect("No videos found")
     );
+    println!(
+        "Publishers: {}",
+        g.v(())
+            .has_label("publisher")
+            .count()
+            .next()
+            .expect("Failed to count publishers")
+            .expect("No publishers found")
+    );
     println!(
         "Likes: {}",
         g.e(())
@@ -255,4 +297,11 @@ pub fn store() {
             .expect("Failed to count recommendations")
             .expect("No recommendations found")
     );
+    // Let the recommend service know the graph changed so that it reloads its snapshot
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .expect("Time went backwards")
+        .as_secs();
+    std::fs::write("../graph_updated_at", now.to_string())
+        .expect("Failed to write ../graph_updated_at");
 }
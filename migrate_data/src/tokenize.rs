@@ -28,7 +28,7 @@ fn tokenize(text: &str) -> Vec<String> {
     tokens
 }
 
-fn compute_tf_matrix(videos: &[VideosJson]) -> (Array2<f64>, HashMap<String, usize>) {
+pub fn compute_tf_matrix(videos: &[VideosJson]) -> (Array2<f64>, HashMap<String, usize>) {
     let mut word_to_idx = HashMap::new();
     let mut word_idx = 0;
     let mut tf_matrix: Vec<Vec<f64>> = Vec::new();
@@ -74,7 +74,7 @@ fn compute_tf_matrix(videos: &[VideosJson]) -> (Array2<f64>, HashMap<String, usi
 //     panic!("Not implemented yet")
 // }
 
-fn compute_cosine_similarity(matrix: &Array2<f64>) -> Array2<f64> {
+pub fn compute_cosine_similarity(matrix: &Array2<f64>) -> Array2<f64> {
     let row_norms = matrix.map_axis(ndarray::Axis(1), |row| row.norm_l2());
     let normalized_matrix = matrix / &row_norms.insert_axis(ndarray::Axis(1));
     let mut similarity_matrix = Array2::eye(matrix.nrows());
@@ -0,0 +1,186 @@
+use std::collections::HashMap;
+
+use axum::{
+    extract::{
+        rejection::{PathRejection, QueryRejection},
+        Path, Query, State,
+    },
+    Json,
+};
+use serde::Serialize;
+
+use crate::{
+    config::Config,
+    error::AppResult,
+    models::{get_limit, AudienceParams, Dismissal, Interaction, VideoMeta},
+    popularity::now,
+    scoring::{aggregate, history_weights, Aggregation},
+    state::AppState,
+};
+
+#[derive(Debug, Serialize)]
+pub struct AudienceMember {
+    pub user_id: String,
+    // Predicted affinity of the user with the video
+    pub score: f64,
+    // Number of videos of the user history similar to the video
+    pub similar_videos: usize,
+}
+
+pub struct AudienceOptions {
+    pub aggregation: Aggregation,
+    pub top_n: usize,
+    pub min_score: f64,
+}
+
+pub fn rank_audience(
+    video: &VideoMeta,
+    similarities: &HashMap<String, f64>,
+    interactions: &[Interaction],
+    dismissals: &[Dismissal],
+    config: &Config,
+    options: &AudienceOptions,
+) -> Vec<AudienceMember> {
+    // The inverse of the content recommendations: users are scored by the similarity of their
+    // history to the video, aggregated like for the recommendations. Users who already liked or
+    // watched the video, dismissed it or blocked its publisher are left out.
+    let mut histories: HashMap<&str, Vec<Interaction>> = HashMap::new();
+    for interaction in interactions {
+        histories
+            .entry(interaction.user_id.as_str())
+            .or_default()
+            .push(interaction.clone());
+    }
+    for dismissal in dismissals {
+        if dismissal.video_id == video.video_id
+            || (dismissal.block_publisher && dismissal.publisher_id == video.publisher_id)
+        {
+            histories.remove(dismissal.user_id.as_str());
+        }
+    }
+
+    let now = now();
+    let mut audience: Vec<AudienceMember> = histories
+        .into_iter()
+        .filter(|(_, history)| {
+            !history
+                .iter()
+                .any(|interaction| interaction.video_id == video.video_id)
+        })
+        .filter_map(|(user_id, history)| {
+            let weighted_similarities: Vec<(f64, f64)> = history_weights(&history, config, now)
+                .iter()
+                .filter_map(|(video_id, weight)| Some((*weight, *similarities.get(video_id)?)))
+                .collect();
+            let score = aggregate(&weighted_similarities, options.aggregation, options.top_n)?;
+            (score >= options.min_score).then(|| AudienceMember {
+                user_id: user_id.to_string(),
+                score,
+                similar_videos: weighted_similarities.len(),
+            })
+        })
+        .collect();
+    audience.sort_by(|a, b| {
+        b.score
+            .total_cmp(&a.score)
+            .then_with(|| a.user_id.cmp(&b.user_id))
+    });
+    audience
+}
+
+pub async fn get_video_audience(
+    State(state): State<AppState>,
+    path: Result<Path<String>, PathRejection>,
+    query: Result<Query<AudienceParams>, QueryRejection>,
+) -> AppResult<Json<Vec<AudienceMember>>> {
+    let Path(video_id) = path?;
+    let Query(query_params) = query?;
+    let limit = get_limit(query_params.limit)?;
+    let options = AudienceOptions {
+        aggregation: query_params.aggregation.unwrap_or(state.config.aggregation),
+        top_n: query_params.top_n.unwrap_or(state.config.top_n),
+        min_score: query_params.min_score.unwrap_or(0.0),
+    };
+
+    let data = state.data();
+    let audience = rank_audience(
+        data.get_video(&video_id).await?,
+        &data.get_similar_videos(&video_id).await?,
+        data.get_interactions().await?,
+        &data.get_dismissals().await?,
+        &state.config,
+        &options,
+    );
+    Ok(Json(audience.into_iter().take(limit).collect()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        config::tests::config,
+        context::tests::{ids, like, video, watch},
+    };
+
+    #[test]
+    fn audience_is_the_users_closest_to_the_video() {
+        let target = video("target", "publisher");
+        let similarities = HashMap::from([("a".to_string(), 0.9), ("b".to_string(), 0.3)]);
+        let interactions = vec![
+            like("fan", "a"),
+            like("casual", "a"),
+            watch("casual", "b"),
+            like("unrelated", "c"),
+            like("seen", "a"),
+            watch("seen", "target"),
+            like("dismissed", "a"),
+            like("blocked", "a"),
+        ];
+        let dismissal = |user_id: &str, video_id: &str, block_publisher: bool| Dismissal {
+            user_id: user_id.to_string(),
+            video_id: video_id.to_string(),
+            publisher_id: "publisher".to_string(),
+            block_publisher,
+            timestamp: None,
+        };
+        let dismissals = vec![
+            dismissal("dismissed", "target", false),
+            dismissal("blocked", "other", true),
+        ];
+        let options = AudienceOptions {
+            aggregation: Aggregation::Mean,
+            top_n: 3,
+            min_score: 0.0,
+        };
+        let audience = rank_audience(
+            &target,
+            &similarities,
+            &interactions,
+            &dismissals,
+            &config(),
+            &options,
+        );
+
+        assert_eq!(
+            ids(&audience, |member| &member.user_id),
+            vec!["fan", "casual"]
+        );
+        assert!((audience[0].score - 0.9).abs() < 1e-9);
+        assert!((audience[1].score - 0.6).abs() < 1e-9);
+        assert_eq!(audience[1].similar_videos, 2);
+
+        let options = AudienceOptions {
+            min_score: 0.7,
+            ..options
+        };
+        let audience = rank_audience(
+            &target,
+            &similarities,
+            &interactions,
+            &dismissals,
+            &config(),
+            &options,
+        );
+        assert_eq!(audience.len(), 1);
+    }
+}
@@ -0,0 +1,185 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::{
+    context::{RecommendationContext, RecommendationOptions},
+    hybrid::Strategy,
+    models::{Interaction, Similarities, VideoMeta},
+    pipeline::Scorer,
+    scoring::{aggregate, dismissal_penalty},
+};
+
+pub fn item_similarities(interactions: &[Interaction]) -> Similarities {
+    // Cosine similarity between the sets of users who liked or watched each video
+    let mut users_by_video: HashMap<&str, HashSet<&str>> = HashMap::new();
+    for interaction in interactions {
+        users_by_video
+            .entry(&interaction.video_id)
+            .or_default()
+            .insert(&interaction.user_id);
+    }
+
+    let mut similarities = Similarities::new();
+    for (video_a, users_a) in users_by_video.iter() {
+        for (video_b, users_b) in users_by_video.iter() {
+            if video_a == video_b {
+                continue;
+            }
+            let common = users_a.intersection(users_b).count();
+            if common == 0 {
+                continue;
+            }
+            let similarity = common as f64 / ((users_a.len() * users_b.len()) as f64).sqrt();
+            similarities
+                .entry(video_a.to_string())
+                .or_default()
+                .insert(video_b.to_string(), similarity);
+        }
+    }
+    similarities
+}
+
+pub fn collaborative_scores(
+    history_weights: &HashMap<String, f64>,
+    dismissed: &HashSet<String>,
+    similarities: &Similarities,
+    options: &RecommendationOptions,
+) -> HashMap<String, f64> {
+    // Score the videos co-consumed with the history, aggregated like the content similarities,
+    // and lowered when co-consumed with the dismissed videos
+
+    let mut weighted_similarities: HashMap<&str, Vec<(f64, f64)>> = HashMap::new();
+    for (watched_video_id, weight) in history_weights {
+        if let Some(neighbors) = similarities.get(watched_video_id) {
+            for (video_id, similarity) in neighbors {
+                if !history_weights.contains_key(video_id) {
+                    weighted_similarities
+                        .entry(video_id)
+                        .or_default()
+                        .push((*weight, *similarity));
+                }
+            }
+        }
+    }
+
+    weighted_similarities
+        .into_iter()
+        .filter_map(|(video_id, pairs)| {
+            let score = aggregate(&pairs, options.aggregation, options.top_n)?;
+            let penalty = dismissal_penalty(
+                dismissed.iter().filter_map(|dismissed_video_id| {
+                    similarities.get(dismissed_video_id)?.get(video_id).copied()
+                }),
+                options.dismiss_weight,
+            );
+            Some((video_id.to_string(), score - penalty))
+        })
+        .collect()
+}
+
+pub struct CollaborativeScorer;
+
+impl Scorer for CollaborativeScorer {
+    fn strategy(&self) -> Strategy {
+        Strategy::Collaborative
+    }
+
+    fn score(
+        &self,
+        context: &RecommendationContext,
+        candidates: &[VideoMeta],
+    ) -> HashMap<String, f64> {
+        let mut scores = collaborative_scores(
+            &context.history,
+            &context.blocklist.videos,
+            &context.item_similarities,
+            &context.options,
+        );
+        let candidates: HashSet<&str> = candidates
+            .iter()
+            .map(|video| video.video_id.as_str())
+            .collect();
+        scores.retain(|video_id, _| candidates.contains(video_id.as_str()));
+        scores
+    }
+
+    fn explain(&self, context: &RecommendationContext, video_id: &str) -> Vec<(String, f64)> {
+        // Videos of the history consumed by the same users as the video
+        context
+            .history
+            .iter()
+            .filter_map(|(watched_video_id, weight)| {
+                let similarity = context
+                    .item_similarities
+                    .get(watched_video_id)?
+                    .get(video_id)?;
+                Some((watched_video_id.clone(), weight * similarity))
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::borrow::Cow;
+
+    use super::*;
+    use crate::{
+        context::tests::{context, like, options, video, watch},
+        hybrid::BlendWeights,
+    };
+
+    #[test]
+    fn item_similarities_are_the_cosine_of_the_audiences() {
+        let similarities = item_similarities(&[
+            like("alice", "a"),
+            watch("alice", "b"),
+            like("bob", "a"),
+            watch("bob", "a"),
+            like("carol", "b"),
+            like("carol", "c"),
+        ]);
+
+        // `a` has 2 users, `b` 2 users and they share 1 of them
+        assert!((similarities["a"]["b"] - 0.5).abs() < 1e-9);
+        assert!((similarities["b"]["a"] - 0.5).abs() < 1e-9);
+        assert!((similarities["b"]["c"] - 1.0 / 2.0_f64.sqrt()).abs() < 1e-9);
+        assert!(!similarities["a"].contains_key("c"));
+        assert!(!similarities["a"].contains_key("a"));
+    }
+
+    #[test]
+    fn collaborative_scores_the_unseen_candidates() {
+        let videos = vec![
+            video("a", "publisher"),
+            video("b", "publisher"),
+            video("c", "publisher"),
+            video("d", "publisher"),
+        ];
+        let mut options = options();
+        options.weights = BlendWeights::from([(Strategy::Collaborative, 1.0)]);
+        options.dismiss_weight = 0.5;
+        let mut context = context(&videos, &[("a", 1.0), ("b", 1.0)], options);
+        context.item_similarities = Cow::Owned(item_similarities(&[
+            like("alice", "a"),
+            like("alice", "b"),
+            like("alice", "c"),
+            like("bob", "a"),
+            like("bob", "d"),
+            like("carol", "d"),
+            like("carol", "x"),
+        ]));
+        let scores = CollaborativeScorer.score(&context, &videos[2..3]);
+
+        // `d` isn't a candidate and the history isn't scored
+        assert_eq!(scores.len(), 1);
+        let a_c = 1.0 / 2.0_f64.sqrt();
+        assert!((scores["c"] - (a_c + 1.0) / 2.0).abs() < 1e-9);
+
+        // Videos co-consumed with a dismissed one are lowered
+        context.blocklist.videos.insert("x".to_string());
+        let scores = CollaborativeScorer.score(&context, &videos[3..]);
+        let a_d = 1.0 / 2.0;
+        let x_d = 1.0 / 2.0_f64.sqrt();
+        assert!((scores["d"] - (a_d - 0.5 * x_d)).abs() < 1e-9);
+    }
+}
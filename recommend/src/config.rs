@@ -0,0 +1,218 @@
+use std::{collections::HashMap, str::FromStr};
+
+use crate::{
+    feed::ShelfKind,
+    hybrid::{parse_weights, BlendWeights},
+    scoring::Aggregation,
+};
+
+const DEFAULT_BLEND_PROFILES: &str = "default=content:0.6,collaborative:0.3,popularity:0.1;\
+    content=content:1;\
+    discovery=content:0.7,collaborative:0.3";
+
+#[derive(Debug, Clone)]
+pub struct Config {
+    // Weight of a liked video in the user history
+    pub like_weight: f64,
+    // Weight of a watched video, multiplied by the watched percentage
+    pub watch_weight: f64,
+    // Weight added for each additional view of the same video
+    pub repeat_view_weight: f64,
+    // How the similarities to the videos of the history are combined
+    pub aggregation: Aggregation,
+    // Number of videos of the history used by the `top_n` aggregation
+    pub top_n: usize,
+    // Days after which an interaction of the history counts half as much, 0 disables the decay
+    pub history_half_life_days: f64,
+    // How much being close to a dismissed video lowers the score of a video
+    pub dismiss_weight: f64,
+    // Named sets of strategy weights, selected per request with `profile=`
+    pub blend_profiles: HashMap<String, BlendWeights>,
+    // Profile used when the request doesn't ask for one
+    pub blend_profile: String,
+    // Maximum number of videos of a same publisher in a page, 0 for no cap
+    pub max_per_publisher: usize,
+    // Whether publishers with fewer impressions are favored by default
+    pub exposure_fairness: bool,
+    // How much the exposure of a publisher lowers the rank of its videos, between 0 and 1
+    pub fairness_strength: f64,
+    // Names of the stages of the recommendation pipeline, in the order they run
+    pub pipeline_generators: Vec<String>,
+    pub pipeline_scorers: Vec<String>,
+    pub pipeline_filters: Vec<String>,
+    pub pipeline_rerankers: Vec<String>,
+    // Gremlin server and the size of the pool of connections to it
+    pub graph_host: String,
+    pub graph_port: u16,
+    pub graph_pool_size: u32,
+    // Seconds a query may take, waiting for a connection of the pool included
+    pub graph_timeout_seconds: u64,
+    // Number of users of a batch computed at the same time, and the most a batch can have
+    pub batch_concurrency: usize,
+    pub batch_max_users: usize,
+    // Whether recommendations are served from an in-memory snapshot of the graph
+    pub snapshot: bool,
+    // File whose modification triggers a reload of the snapshot, empty to disable
+    pub snapshot_watch_file: String,
+    // Seconds between two checks of the watched file
+    pub snapshot_poll_seconds: u64,
+    // Seconds to wait after an event changed the graph before reloading the snapshot
+    pub snapshot_reload_delay_seconds: u64,
+    // Share of the history above which a publisher is not recommended, the user already knows it
+    pub heavy_publisher_share: f64,
+    // Shelves of the home feed, in the order they are shown
+    pub feed_shelves: Vec<ShelfKind>,
+    pub feed_shelf_size: i32,
+    // Number of "Because you watched" shelves
+    pub feed_recent_videos: usize,
+}
+
+fn get_env<T: FromStr>(key: &str, default: T) -> T {
+    match std::env::var(key) {
+        Ok(value) => value
+            .parse()
+            .unwrap_or_else(|_| panic!("{} has an invalid value: {}", key, value)),
+        Err(_) => default,
+    }
+}
+
+fn get_env_list(key: &str, default: &str) -> Vec<String> {
+    // Comma separated list of names
+    std::env::var(key)
+        .unwrap_or_else(|_| default.to_string())
+        .split(',')
+        .map(|name| name.trim().to_string())
+        .filter(|name| !name.is_empty())
+        .collect()
+}
+
+fn parse_profiles(value: &str) -> HashMap<String, BlendWeights> {
+    // Profiles are written as `name=strategy:weight,...;name=...`
+    value
+        .split(';')
+        .filter(|profile| !profile.trim().is_empty())
+        .map(|profile| {
+            let (name, weights) = profile
+                .split_once('=')
+                .unwrap_or_else(|| panic!("BLEND_PROFILES has an invalid profile: {}", profile));
+            let weights = parse_weights(weights)
+                .unwrap_or_else(|err| panic!("BLEND_PROFILES has an invalid profile: {}", err));
+            (name.trim().to_string(), weights)
+        })
+        .collect()
+}
+
+impl Config {
+    pub fn from_env() -> Config {
+        dotenvy::dotenv().ok();
+
+        let config = Config {
+            like_weight: get_env("LIKE_WEIGHT", 1.0),
+            watch_weight: get_env("WATCH_WEIGHT", 1.0),
+            repeat_view_weight: get_env("REPEAT_VIEW_WEIGHT", 0.25),
+            aggregation: get_env("AGGREGATION", Aggregation::Mean),
+            top_n: get_env("AGGREGATION_TOP_N", 3),
+            history_half_life_days: get_env("HISTORY_HALF_LIFE_DAYS", 90.0),
+            dismiss_weight: get_env("DISMISS_WEIGHT", 1.0),
+            blend_profiles: parse_profiles(
+                &std::env::var("BLEND_PROFILES")
+                    .unwrap_or_else(|_| DEFAULT_BLEND_PROFILES.to_string()),
+            ),
+            blend_profile: get_env("BLEND_PROFILE", "default".to_string()),
+            max_per_publisher: get_env("MAX_PER_PUBLISHER", 3),
+            exposure_fairness: get_env("EXPOSURE_FAIRNESS", false),
+            fairness_strength: get_env("FAIRNESS_STRENGTH", 0.5),
+            pipeline_generators: get_env_list("PIPELINE_GENERATORS", "unseen"),
+            pipeline_scorers: get_env_list("PIPELINE_SCORERS", "content,collaborative,popularity"),
+            pipeline_filters: get_env_list("PIPELINE_FILTERS", "seen"),
+            pipeline_rerankers: get_env_list(
+                "PIPELINE_RERANKERS",
+                "fairness,diversity,publisher_cap",
+            ),
+            graph_host: get_env("GRAPH_HOST", "localhost".to_string()),
+            graph_port: get_env("GRAPH_PORT", 8182),
+            graph_pool_size: get_env("GRAPH_POOL_SIZE", 10),
+            graph_timeout_seconds: get_env("GRAPH_TIMEOUT_SECONDS", 30),
+            batch_concurrency: get_env("BATCH_CONCURRENCY", 8),
+            batch_max_users: get_env("BATCH_MAX_USERS", 5000),
+            snapshot: get_env("SNAPSHOT", true),
+            snapshot_watch_file: get_env("SNAPSHOT_WATCH_FILE", "../graph_updated_at".to_string()),
+            snapshot_poll_seconds: get_env("SNAPSHOT_POLL_SECONDS", 5),
+            snapshot_reload_delay_seconds: get_env("SNAPSHOT_RELOAD_DELAY_SECONDS", 2),
+            heavy_publisher_share: get_env("HEAVY_PUBLISHER_SHARE", 0.3),
+            feed_shelves: get_env_list(
+                "FEED_SHELVES",
+                "continue_watching,because_you_watched,trending,new_from_publishers",
+            )
+            .iter()
+            .map(|name| {
+                name.parse()
+                    .unwrap_or_else(|err| panic!("FEED_SHELVES: {}", err))
+            })
+            .collect(),
+            feed_shelf_size: get_env("FEED_SHELF_SIZE", 10),
+            feed_recent_videos: get_env("FEED_RECENT_VIDEOS", 3),
+        };
+        if !config.blend_profiles.contains_key(&config.blend_profile) {
+            panic!(
+                "BLEND_PROFILE {} is not in BLEND_PROFILES",
+                config.blend_profile
+            );
+        }
+        config
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+
+    pub fn config() -> Config {
+        // The defaults, without reading the environment
+        Config {
+            like_weight: 1.0,
+            watch_weight: 1.0,
+            repeat_view_weight: 0.25,
+            aggregation: Aggregation::Mean,
+            top_n: 3,
+            history_half_life_days: 90.0,
+            dismiss_weight: 1.0,
+            blend_profiles: parse_profiles(DEFAULT_BLEND_PROFILES),
+            blend_profile: "default".to_string(),
+            max_per_publisher: 3,
+            exposure_fairness: false,
+            fairness_strength: 0.5,
+            pipeline_generators: vec!["unseen".to_string()],
+            pipeline_scorers: vec![
+                "content".to_string(),
+                "collaborative".to_string(),
+                "popularity".to_string(),
+            ],
+            pipeline_filters: vec!["seen".to_string()],
+            pipeline_rerankers: vec![
+                "fairness".to_string(),
+                "diversity".to_string(),
+                "publisher_cap".to_string(),
+            ],
+            graph_host: "localhost".to_string(),
+            graph_port: 8182,
+            graph_pool_size: 10,
+            graph_timeout_seconds: 30,
+            batch_concurrency: 8,
+            batch_max_users: 5000,
+            snapshot: true,
+            snapshot_watch_file: String::new(),
+            snapshot_poll_seconds: 5,
+            snapshot_reload_delay_seconds: 2,
+            heavy_publisher_share: 0.3,
+            feed_shelves: vec![
+                ShelfKind::ContinueWatching,
+                ShelfKind::BecauseYouWatched,
+                ShelfKind::Trending,
+                ShelfKind::NewFromPublishers,
+            ],
+            feed_shelf_size: 10,
+            feed_recent_videos: 3,
+        }
+    }
+}
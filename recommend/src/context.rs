@@ -0,0 +1,382 @@
+use std::{
+    borrow::Cow,
+    collections::{HashMap, HashSet},
+};
+
+use crate::{
+    config::Config,
+    error::{AppError, AppResult},
+    hybrid::{get_blend_weights, BlendWeights, Strategy},
+    models::{
+        get_limit, split_list, Dismissal, Params, Similarities, VideoMeta, VideoRecommendation,
+        MAX_OFFSET,
+    },
+    scoring::Aggregation,
+    source::DataSource,
+};
+
+#[derive(Debug, Clone)]
+pub struct RecommendationOptions {
+    pub limit: usize,
+    pub aggregation: Aggregation,
+    pub top_n: usize,
+    pub weights: BlendWeights,
+    pub diversity: f64,
+    pub max_per_publisher: usize,
+    pub fairness: bool,
+    pub offset: usize,
+    // Filters of the request, applied before the results are truncated
+    pub exclude: HashSet<String>,
+    pub include_publishers: HashSet<String>,
+    pub exclude_publishers: HashSet<String>,
+    pub min_score: Option<f32>,
+    // How much being close to a dismissed video lowers a score
+    pub dismiss_weight: f64,
+}
+
+fn get_offset(query_params: &Params) -> AppResult<usize> {
+    // The cursor is the offset of the next page, it wins over `offset`
+    let offset = match &query_params.cursor {
+        Some(cursor) => cursor
+            .parse()
+            .map_err(|_| AppError::BadRequest(format!("Invalid cursor: {}", cursor)))?,
+        None => query_params.offset.unwrap_or(0),
+    };
+    if offset > MAX_OFFSET {
+        return Err(AppError::BadRequest(format!(
+            "offset must be at most {}, got {}",
+            MAX_OFFSET, offset
+        )));
+    }
+    Ok(offset)
+}
+
+// A page of recommendations, with the offset of the next one when there are recommendations left
+pub struct Page {
+    pub videos: Vec<VideoRecommendation>,
+    pub next_offset: Option<usize>,
+}
+
+impl RecommendationOptions {
+    pub fn new(query_params: &Params, config: &Config) -> AppResult<RecommendationOptions> {
+        // Query parameters override the configuration
+        Ok(RecommendationOptions {
+            limit: get_limit(query_params.limit)?,
+            aggregation: query_params.aggregation.unwrap_or(config.aggregation),
+            top_n: query_params.top_n.unwrap_or(config.top_n),
+            weights: get_blend_weights(config, query_params)?,
+            diversity: query_params.diversity.unwrap_or(0.0).clamp(0.0, 1.0),
+            max_per_publisher: query_params
+                .max_per_publisher
+                .unwrap_or(config.max_per_publisher),
+            fairness: query_params.fairness.unwrap_or(config.exposure_fairness),
+            offset: get_offset(query_params)?,
+            exclude: split_list(&query_params.exclude).into_iter().collect(),
+            include_publishers: split_list(&query_params.publisher)
+                .into_iter()
+                .filter(|publisher| !publisher.starts_with('-'))
+                .collect(),
+            exclude_publishers: split_list(&query_params.publisher)
+                .into_iter()
+                .filter_map(|publisher| publisher.strip_prefix('-').map(str::to_string))
+                .collect(),
+            min_score: query_params.min_score,
+            dismiss_weight: config.dismiss_weight,
+        })
+    }
+
+    pub fn keeps(&self, recommendation: &VideoRecommendation) -> bool {
+        !self.exclude.contains(&recommendation.id)
+            && (self.include_publishers.is_empty()
+                || self
+                    .include_publishers
+                    .contains(&recommendation.publisher_id))
+            && !self
+                .exclude_publishers
+                .contains(&recommendation.publisher_id)
+            && self
+                .min_score
+                .is_none_or(|min_score| recommendation.score >= min_score)
+    }
+
+    pub fn page(&self, recommendations: Vec<VideoRecommendation>) -> Page {
+        let end = self.offset.saturating_add(self.limit);
+        let next_offset = (recommendations.len() > end).then_some(end);
+        Page {
+            videos: recommendations
+                .into_iter()
+                .skip(self.offset)
+                .take(self.limit)
+                .collect(),
+            next_offset,
+        }
+    }
+
+    pub fn uses(&self, strategy: Strategy) -> bool {
+        self.weights
+            .get(&strategy)
+            .is_some_and(|weight| *weight > 0.0)
+    }
+}
+
+// Videos the user doesn't want to see, and the publishers they blocked
+#[derive(Debug, Default)]
+pub struct Blocklist {
+    pub videos: HashSet<String>,
+    pub publishers: HashSet<String>,
+}
+
+impl Blocklist {
+    pub fn new(dismissals: &[Dismissal]) -> Blocklist {
+        Blocklist {
+            videos: dismissals
+                .iter()
+                .map(|dismissal| dismissal.video_id.clone())
+                .collect(),
+            publishers: dismissals
+                .iter()
+                .filter(|dismissal| dismissal.block_publisher)
+                .map(|dismissal| dismissal.publisher_id.clone())
+                .collect(),
+        }
+    }
+
+    pub fn blocks(&self, recommendation: &VideoRecommendation) -> bool {
+        self.videos.contains(&recommendation.id)
+            || self.publishers.contains(&recommendation.publisher_id)
+    }
+}
+
+// Everything the pipeline stages need, loaded before running them so that they don't touch the graph.
+// The catalog-wide data is borrowed from the data source, it isn't copied for each user.
+pub struct RecommendationContext<'a> {
+    pub options: RecommendationOptions,
+    // Weight of each video of the user history
+    pub history: HashMap<String, f64>,
+    pub blocklist: Blocklist,
+    // Every video of the catalog
+    pub videos: &'a [VideoMeta],
+    // Number of likes and completed watches of each video, only loaded for the popularity strategy
+    pub popularity: Cow<'a, HashMap<String, f64>>,
+    // Similarities between the videos consumed by the same users, only loaded for the collaborative strategy
+    pub item_similarities: Cow<'a, Similarities>,
+    // For each video not seen, its similarity to the videos of the history and the dismissed ones
+    pub content_similarities: HashMap<String, Vec<(String, f64)>>,
+    // Similarities between the videos not seen, only loaded for the diversity re-ranking
+    pub similarities: Similarities,
+}
+
+impl RecommendationContext<'_> {
+    pub fn is_seen(&self, video_id: &str) -> bool {
+        self.history.contains_key(video_id)
+    }
+
+    pub fn title(&self, video_id: &str) -> Option<String> {
+        self.videos
+            .iter()
+            .find(|video| video.video_id == video_id)
+            .map(|video| video.title.clone())
+    }
+}
+
+pub async fn load_context(
+    data: &DataSource,
+    history: HashMap<String, f64>,
+    blocklist: Blocklist,
+    options: RecommendationOptions,
+) -> AppResult<RecommendationContext<'_>> {
+    // Load what the requested strategies and re-rankings need
+    let videos = data.get_videos().await?;
+    let not_seen: Vec<String> = videos
+        .iter()
+        .filter(|video| !history.contains_key(&video.video_id))
+        .map(|video| video.video_id.clone())
+        .collect();
+    println!("not_watched_videos {}", not_seen.len());
+
+    let popularity = if options.uses(Strategy::Popularity) {
+        Cow::Borrowed(data.get_popularity().await?)
+    } else {
+        Cow::Owned(HashMap::new())
+    };
+    let item_similarities = if options.uses(Strategy::Collaborative) {
+        Cow::Borrowed(data.get_item_similarities().await?)
+    } else {
+        Cow::Owned(Similarities::new())
+    };
+    let content_similarities = if options.uses(Strategy::Content) {
+        let source_video_ids: Vec<String> = history
+            .keys()
+            .chain(blocklist.videos.iter())
+            .cloned()
+            .collect();
+        data.get_content_similarities(&source_video_ids, &not_seen)
+            .await?
+    } else {
+        HashMap::new()
+    };
+    let similarities = if options.diversity > 0.0 {
+        data.get_similarities_between(&not_seen).await?
+    } else {
+        Similarities::new()
+    };
+
+    Ok(RecommendationContext {
+        options,
+        history,
+        blocklist,
+        videos,
+        popularity,
+        item_similarities,
+        content_similarities,
+        similarities,
+    })
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use crate::{
+        config::tests::config,
+        models::{Interaction, InteractionKind},
+    };
+
+    pub fn video(video_id: &str, publisher_id: &str) -> VideoMeta {
+        VideoMeta {
+            video_id: video_id.to_string(),
+            title: format!("Title of {}", video_id),
+            publisher_id: publisher_id.to_string(),
+        }
+    }
+
+    pub fn interaction(
+        user_id: &str,
+        video_id: &str,
+        kind: InteractionKind,
+        timestamp: Option<i64>,
+    ) -> Interaction {
+        Interaction {
+            user_id: user_id.to_string(),
+            video_id: video_id.to_string(),
+            kind,
+            watch_percentage: (kind == InteractionKind::Watch).then_some(1.0),
+            timestamp,
+            seed: false,
+        }
+    }
+
+    pub fn like(user_id: &str, video_id: &str) -> Interaction {
+        interaction(user_id, video_id, InteractionKind::Like, None)
+    }
+
+    pub fn watch(user_id: &str, video_id: &str) -> Interaction {
+        interaction(user_id, video_id, InteractionKind::Watch, None)
+    }
+
+    pub fn similarities(edges: &[(&str, &str, f64)]) -> Similarities {
+        // Edges are added both ways, like the ones `migrate_data` stores
+        let mut similarities = Similarities::new();
+        for (video_a, video_b, similarity) in edges {
+            for (from, to) in [(video_a, video_b), (video_b, video_a)] {
+                similarities
+                    .entry(from.to_string())
+                    .or_default()
+                    .insert(to.to_string(), *similarity);
+            }
+        }
+        similarities
+    }
+
+    pub fn recommendation(video_id: &str, publisher_id: &str, score: f32) -> VideoRecommendation {
+        VideoRecommendation {
+            id: video_id.to_string(),
+            title: format!("Title of {}", video_id),
+            publisher_id: publisher_id.to_string(),
+            score,
+            contributions: None,
+            reasons: None,
+        }
+    }
+
+    pub fn ids<T>(items: &[T], id: impl Fn(&T) -> &str) -> Vec<String> {
+        items.iter().map(|item| id(item).to_string()).collect()
+    }
+
+    pub fn options() -> RecommendationOptions {
+        // Content only, without re-ranking nor filters
+        RecommendationOptions {
+            limit: 10,
+            aggregation: Aggregation::Mean,
+            top_n: 3,
+            weights: BlendWeights::from([(Strategy::Content, 1.0)]),
+            diversity: 0.0,
+            max_per_publisher: 0,
+            fairness: false,
+            offset: 0,
+            exclude: HashSet::new(),
+            include_publishers: HashSet::new(),
+            exclude_publishers: HashSet::new(),
+            min_score: None,
+            dismiss_weight: 0.0,
+        }
+    }
+
+    pub fn context<'a>(
+        videos: &'a [VideoMeta],
+        history: &[(&str, f64)],
+        options: RecommendationOptions,
+    ) -> RecommendationContext<'a> {
+        RecommendationContext {
+            options,
+            history: history
+                .iter()
+                .map(|(video_id, weight)| (video_id.to_string(), *weight))
+                .collect(),
+            blocklist: Blocklist::default(),
+            videos,
+            popularity: Cow::Owned(HashMap::new()),
+            item_similarities: Cow::Owned(Similarities::new()),
+            content_similarities: HashMap::new(),
+            similarities: Similarities::new(),
+        }
+    }
+
+    #[test]
+    fn offset_is_bounded() {
+        let params = |cursor: &str| Params {
+            cursor: Some(cursor.to_string()),
+            ..Params::default()
+        };
+        let options = RecommendationOptions::new(&params("20"), &config()).unwrap();
+        assert_eq!(options.offset, 20);
+        assert!(RecommendationOptions::new(&params("10000"), &config()).is_ok());
+        assert!(matches!(
+            RecommendationOptions::new(&params("10001"), &config()),
+            Err(AppError::BadRequest(_))
+        ));
+        assert!(matches!(
+            RecommendationOptions::new(&params("next"), &config()),
+            Err(AppError::BadRequest(_))
+        ));
+    }
+
+    #[test]
+    fn page_gives_the_next_offset_while_videos_remain() {
+        let recommendations = || {
+            (0..5)
+                .map(|index| recommendation(&index.to_string(), "publisher", 1.0))
+                .collect()
+        };
+        let mut options = options();
+        options.limit = 2;
+        options.offset = 2;
+        let page = options.page(recommendations());
+        assert_eq!(ids(&page.videos, |video| &video.id), vec!["2", "3"]);
+        assert_eq!(page.next_offset, Some(4));
+
+        options.offset = 3;
+        let page = options.page(recommendations());
+        assert_eq!(ids(&page.videos, |video| &video.id), vec!["3", "4"]);
+        assert_eq!(page.next_offset, None);
+    }
+}
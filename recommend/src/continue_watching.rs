@@ -0,0 +1,227 @@
+use std::{cmp::Reverse, collections::HashMap, str::FromStr};
+
+use axum::{
+    extract::{
+        rejection::{PathRejection, QueryRejection},
+        Path, Query, State,
+    },
+    Json,
+};
+use serde::Serialize;
+use uuid::Uuid;
+
+use crate::{
+    context::Blocklist,
+    error::{AppError, AppResult},
+    models::{
+        get_limit, ContinueWatchingParams, Interaction, InteractionKind, PartialView, VideoMeta,
+    },
+    state::AppState,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ContinueWatchingOrder {
+    // Most recently watched first, then least remaining time
+    Recent,
+    // Least remaining time first, then most recently watched
+    Remaining,
+}
+
+impl FromStr for ContinueWatchingOrder {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "recent" => Ok(ContinueWatchingOrder::Recent),
+            "remaining" => Ok(ContinueWatchingOrder::Remaining),
+            _ => Err(format!("Unknown order: {}", value)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ContinueWatching {
+    pub id: String,
+    pub title: String,
+    pub publisher_id: String,
+    // Seconds already watched, when known
+    pub watch_time: Option<f64>,
+    pub watch_percentage: f64,
+    // Seconds left, estimated from the time watched and the percentage it represents,
+    // unknown without a time watched
+    pub remaining_time: Option<f64>,
+    pub timestamp: Option<i64>,
+}
+
+pub fn rank_continue_watching(
+    videos: &[VideoMeta],
+    history: &[Interaction],
+    partial_views: &[PartialView],
+    blocklist: &Blocklist,
+    order: ContinueWatchingOrder,
+) -> Vec<ContinueWatching> {
+    // Last partial view of each video, leaving out the videos finished since then
+    let mut latest: HashMap<&str, &PartialView> = HashMap::new();
+    for partial_view in partial_views {
+        let entry = latest
+            .entry(partial_view.video_id.as_str())
+            .or_insert(partial_view);
+        if (partial_view.timestamp, partial_view.watch_percentage)
+            > (entry.timestamp, entry.watch_percentage)
+        {
+            *entry = partial_view;
+        }
+    }
+    for interaction in history {
+        if interaction.kind != InteractionKind::Watch {
+            continue;
+        }
+        let finished_since =
+            latest
+                .get(interaction.video_id.as_str())
+                .is_some_and(|partial_view| {
+                    match (interaction.timestamp, partial_view.timestamp) {
+                        (Some(finished_at), Some(started_at)) => finished_at >= started_at,
+                        _ => true,
+                    }
+                });
+        if finished_since {
+            latest.remove(interaction.video_id.as_str());
+        }
+    }
+
+    let mut ranking: Vec<ContinueWatching> = videos
+        .iter()
+        .filter_map(|video| {
+            let partial_view = latest.get(video.video_id.as_str())?;
+            let remaining_time = partial_view
+                .watch_time
+                .filter(|_| partial_view.watch_percentage > 0.0)
+                .map(|watch_time| {
+                    watch_time * (1.0 - partial_view.watch_percentage)
+                        / partial_view.watch_percentage
+                });
+            Some(ContinueWatching {
+                id: video.video_id.clone(),
+                title: video.title.clone(),
+                publisher_id: video.publisher_id.clone(),
+                watch_time: partial_view.watch_time,
+                watch_percentage: partial_view.watch_percentage,
+                remaining_time,
+                timestamp: partial_view.timestamp,
+            })
+        })
+        .filter(|video| {
+            !blocklist.videos.contains(&video.id)
+                && !blocklist.publishers.contains(&video.publisher_id)
+        })
+        .collect();
+
+    // Unknown remaining times come last
+    let remaining = |video: &ContinueWatching| video.remaining_time.unwrap_or(f64::INFINITY);
+    ranking.sort_by(|a, b| {
+        let by_recency = Reverse(a.timestamp).cmp(&Reverse(b.timestamp));
+        let by_remaining = remaining(a).total_cmp(&remaining(b));
+        match order {
+            ContinueWatchingOrder::Recent => by_recency.then(by_remaining),
+            ContinueWatchingOrder::Remaining => by_remaining.then(by_recency),
+        }
+        .then_with(|| a.id.cmp(&b.id))
+    });
+    ranking
+}
+
+pub async fn get_continue_watching(
+    State(state): State<AppState>,
+    path: Result<Path<Uuid>, PathRejection>,
+    query: Result<Query<ContinueWatchingParams>, QueryRejection>,
+) -> AppResult<Json<Vec<ContinueWatching>>> {
+    let Path(user_id) = path?;
+    let Query(query_params) = query?;
+    let limit = get_limit(query_params.limit)?;
+    let order = match &query_params.order {
+        Some(order) => order.parse().map_err(AppError::BadRequest)?,
+        None => ContinueWatchingOrder::Recent,
+    };
+
+    let data = state.data();
+    data.check_user(user_id).await?;
+    let ranking = rank_continue_watching(
+        data.get_videos().await?,
+        &data.get_user_history(user_id).await?,
+        &data.get_user_partial_views(user_id).await?,
+        &Blocklist::new(&data.get_user_dismissals(user_id).await?),
+        order,
+    );
+    Ok(Json(ranking.into_iter().take(limit).collect()))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use super::*;
+    use crate::context::tests::{ids, interaction, video};
+
+    fn partial_view(
+        video_id: &str,
+        watch_time: Option<f64>,
+        watch_percentage: f64,
+        timestamp: i64,
+    ) -> PartialView {
+        PartialView {
+            user_id: "user".to_string(),
+            video_id: video_id.to_string(),
+            watch_time,
+            watch_percentage,
+            timestamp: Some(timestamp),
+        }
+    }
+
+    #[test]
+    fn continue_watching_keeps_the_unfinished_videos() {
+        let videos = vec![
+            video("a", "publisher"),
+            video("b", "publisher"),
+            video("c", "publisher"),
+            video("d", "publisher"),
+            video("e", "blocked"),
+        ];
+        let history = vec![interaction("user", "c", InteractionKind::Watch, Some(30))];
+        let partial_views = vec![
+            partial_view("a", Some(60.0), 0.2, 10),
+            partial_view("a", Some(300.0), 0.5, 20),
+            partial_view("b", Some(100.0), 0.5, 20),
+            partial_view("c", Some(100.0), 0.5, 25),
+            partial_view("d", None, 0.5, 40),
+            partial_view("e", Some(100.0), 0.5, 50),
+        ];
+        let blocklist = Blocklist {
+            videos: HashSet::new(),
+            publishers: HashSet::from(["blocked".to_string()]),
+        };
+
+        let recent = rank_continue_watching(
+            &videos,
+            &history,
+            &partial_views,
+            &blocklist,
+            ContinueWatchingOrder::Recent,
+        );
+        // `c` was finished since, `e` is blocked
+        assert_eq!(ids(&recent, |video| &video.id), vec!["d", "b", "a"]);
+        // The last view of `a` counts
+        assert_eq!(recent[2].watch_time, Some(300.0));
+        assert_eq!(recent[2].remaining_time, Some(300.0));
+        assert_eq!(recent[0].remaining_time, None);
+
+        let remaining = rank_continue_watching(
+            &videos,
+            &history,
+            &partial_views,
+            &blocklist,
+            ContinueWatchingOrder::Remaining,
+        );
+        assert_eq!(ids(&remaining, |video| &video.id), vec!["b", "a", "d"]);
+    }
+}
@@ -0,0 +1,92 @@
+use axum::{
+    extract::{
+        rejection::{JsonRejection, PathRejection},
+        Path, State,
+    },
+    Json,
+};
+use gremlin_client::GValue;
+use serde::Deserialize;
+use uuid::Uuid;
+
+use crate::{
+    error::{AppError, AppResult},
+    events::{check_exists, reload_on_change, EventResult},
+    graph::EdgeChange,
+    models::Dismissal,
+    popularity::now,
+    state::AppState,
+};
+
+#[derive(Debug, Deserialize)]
+pub struct DismissRequest {
+    pub video_id: String,
+    // Also hide every video of the publisher
+    #[serde(default)]
+    pub block_publisher: bool,
+    pub timestamp: Option<i64>,
+}
+
+pub async fn dismiss(
+    state: &AppState,
+    user_id: Uuid,
+    video_id: &str,
+    block_publisher: bool,
+    timestamp: Option<i64>,
+) -> AppResult<EdgeChange> {
+    // Dismissing again only updates the edge, e.g. to block the publisher afterwards
+    let properties = vec![
+        ("timestamp", GValue::from(timestamp.unwrap_or_else(now))),
+        ("blockPublisher", GValue::from(block_publisher)),
+    ];
+    state
+        .graph
+        .set_user_edge("dismissed", user_id, video_id, properties)
+        .await
+}
+
+pub async fn get_dismissed(
+    State(state): State<AppState>,
+    path: Result<Path<Uuid>, PathRejection>,
+) -> AppResult<Json<Vec<Dismissal>>> {
+    // Read from the graph so that a dismissal shows up before the snapshot is reloaded
+    let Path(user_id) = path?;
+    if !state.graph.user_exists(user_id).await? {
+        return Err(AppError::UnknownUser(user_id));
+    }
+    Ok(Json(state.graph.get_user_dismissals(user_id).await?))
+}
+
+pub async fn post_dismissed(
+    State(state): State<AppState>,
+    path: Result<Path<Uuid>, PathRejection>,
+    body: Result<Json<DismissRequest>, JsonRejection>,
+) -> AppResult<Json<EventResult>> {
+    let Path(user_id) = path?;
+    let Json(request) = body?;
+    check_exists(&state, user_id, &request.video_id).await?;
+    let change = dismiss(
+        &state,
+        user_id,
+        &request.video_id,
+        request.block_publisher,
+        request.timestamp,
+    )
+    .await?;
+    reload_on_change(&state, change);
+    Ok(Json(EventResult { change }))
+}
+
+pub async fn delete_dismissed(
+    State(state): State<AppState>,
+    path: Result<Path<(Uuid, String)>, PathRejection>,
+) -> AppResult<Json<EventResult>> {
+    let Path((user_id, video_id)) = path?;
+    check_exists(&state, user_id, &video_id).await?;
+    let change = state
+        .graph
+        .remove_user_edge("dismissed", user_id, &video_id)
+        .await?;
+    reload_on_change(&state, change);
+    Ok(Json(EventResult { change }))
+}
@@ -0,0 +1,102 @@
+use std::fmt;
+
+use axum::{
+    extract::rejection::{JsonRejection, PathRejection, QueryRejection},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde::Serialize;
+use uuid::Uuid;
+
+#[derive(Debug)]
+pub enum AppError {
+    // The user isn't in the graph
+    UnknownUser(Uuid),
+    // The video isn't in the graph
+    UnknownVideo(String),
+    // A parameter of the request is missing or invalid
+    BadRequest(String),
+    // The graph couldn't be reached or didn't answer in time
+    GraphUnavailable(String),
+    // The graph answered with an error, e.g. a query it couldn't run or a value it couldn't read
+    GraphQuery(String),
+    // Something went wrong on our side, e.g. a task that panicked
+    Internal(String),
+}
+
+pub type AppResult<T> = Result<T, AppError>;
+
+#[derive(Debug, Serialize)]
+pub struct ErrorBody {
+    pub error: &'static str,
+    pub message: String,
+}
+
+impl AppError {
+    fn status(&self) -> StatusCode {
+        match self {
+            AppError::UnknownUser(_) | AppError::UnknownVideo(_) => StatusCode::NOT_FOUND,
+            AppError::BadRequest(_) => StatusCode::BAD_REQUEST,
+            AppError::GraphUnavailable(_) => StatusCode::SERVICE_UNAVAILABLE,
+            AppError::GraphQuery(_) | AppError::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    pub fn body(&self) -> ErrorBody {
+        ErrorBody {
+            error: self.code(),
+            message: self.to_string(),
+        }
+    }
+
+    fn code(&self) -> &'static str {
+        match self {
+            AppError::UnknownUser(_) => "unknown_user",
+            AppError::UnknownVideo(_) => "unknown_video",
+            AppError::BadRequest(_) => "bad_request",
+            AppError::GraphUnavailable(_) => "graph_unavailable",
+            AppError::GraphQuery(_) => "graph_query_failed",
+            AppError::Internal(_) => "internal_error",
+        }
+    }
+}
+
+impl fmt::Display for AppError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AppError::UnknownUser(user_id) => write!(f, "Unknown user {}", user_id),
+            AppError::UnknownVideo(video_id) => write!(f, "Unknown video {}", video_id),
+            AppError::BadRequest(message) => write!(f, "{}", message),
+            AppError::GraphUnavailable(message) => write!(f, "Graph unavailable: {}", message),
+            AppError::GraphQuery(message) => write!(f, "Graph query failed: {}", message),
+            AppError::Internal(message) => write!(f, "Internal error: {}", message),
+        }
+    }
+}
+
+impl From<QueryRejection> for AppError {
+    fn from(rejection: QueryRejection) -> Self {
+        AppError::BadRequest(rejection.body_text())
+    }
+}
+
+impl From<JsonRejection> for AppError {
+    fn from(rejection: JsonRejection) -> Self {
+        AppError::BadRequest(rejection.body_text())
+    }
+}
+
+impl From<PathRejection> for AppError {
+    fn from(rejection: PathRejection) -> Self {
+        AppError::BadRequest(rejection.body_text())
+    }
+}
+
+impl IntoResponse for AppError {
+    fn into_response(self) -> Response {
+        let status = self.status();
+        println!("{} {}", status, self);
+        (status, Json(self.body())).into_response()
+    }
+}
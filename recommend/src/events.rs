@@ -0,0 +1,292 @@
+use axum::{
+    extract::{rejection::JsonRejection, State},
+    Json,
+};
+use gremlin_client::GValue;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::{
+    dismissals::dismiss,
+    error::{AppError, AppResult},
+    graph::EdgeChange,
+    popularity::now,
+    state::AppState,
+};
+
+// Same rule as `migrate_data store`: a video counts as watched once finished or 70% seen
+pub const WATCHED_PERCENTAGE: f64 = 0.7;
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Event {
+    Like {
+        user_id: Uuid,
+        video_id: String,
+        timestamp: Option<i64>,
+    },
+    Unlike {
+        user_id: Uuid,
+        video_id: String,
+    },
+    WatchProgress {
+        user_id: Uuid,
+        video_id: String,
+        // Between 0 and 1
+        watch_percentage: f64,
+        // Seconds watched
+        watch_time: Option<f64>,
+        #[serde(default)]
+        is_watched: bool,
+        timestamp: Option<i64>,
+    },
+    NotInterested {
+        user_id: Uuid,
+        video_id: String,
+        // Also hide every video of the publisher
+        #[serde(default)]
+        block_publisher: bool,
+        timestamp: Option<i64>,
+    },
+}
+
+impl Event {
+    fn ids(&self) -> (Uuid, &str) {
+        match self {
+            Event::Like {
+                user_id, video_id, ..
+            }
+            | Event::Unlike { user_id, video_id }
+            | Event::WatchProgress {
+                user_id, video_id, ..
+            }
+            | Event::NotInterested {
+                user_id, video_id, ..
+            } => (*user_id, video_id),
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct EventResult {
+    // What happened to the edge of the event
+    pub change: EdgeChange,
+}
+
+pub async fn check_exists(state: &AppState, user_id: Uuid, video_id: &str) -> AppResult<()> {
+    // Edges are only written between a user and a video already in the graph
+    if !state.graph.user_exists(user_id).await? {
+        return Err(AppError::UnknownUser(user_id));
+    }
+    if !state.graph.video_exists(video_id).await? {
+        return Err(AppError::UnknownVideo(video_id.to_string()));
+    }
+    Ok(())
+}
+
+pub fn reload_on_change(state: &AppState, change: EdgeChange) {
+    // Let the snapshot catch up, changes coming close together are loaded at once
+    if let (Some(store), EdgeChange::Added | EdgeChange::Updated | EdgeChange::Removed) =
+        (&state.snapshot, change)
+    {
+        store.request_reload();
+    }
+}
+
+pub fn like_properties(timestamp: i64) -> Vec<(&'static str, GValue)> {
+    // A real like on a video picked at onboarding is not a seed like anymore
+    vec![
+        ("timestamp", GValue::from(timestamp)),
+        ("seed", GValue::from(false)),
+    ]
+}
+
+pub fn partial_view_properties(
+    watch_percentage: f64,
+    watch_time: Option<f64>,
+    timestamp: i64,
+) -> (Vec<(&'static str, GValue)>, Vec<&'static str>) {
+    // Properties of the `in_progress` edge, and the ones to remove from it: without a time
+    // watched, the one of an earlier event would not go with the new percentage
+    let mut properties = vec![
+        ("watchedPercentage", GValue::from(watch_percentage)),
+        ("timestamp", GValue::from(timestamp)),
+    ];
+    let mut removed = Vec::new();
+    match watch_time {
+        Some(watch_time) => properties.push(("watchTime", GValue::from(watch_time))),
+        None => removed.push("watchTime"),
+    }
+    (properties, removed)
+}
+
+pub async fn apply_event(state: &AppState, event: Event) -> AppResult<EdgeChange> {
+    let (user_id, video_id) = event.ids();
+    check_exists(state, user_id, video_id).await?;
+
+    match &event {
+        Event::Like { timestamp, .. } => {
+            let properties = like_properties(timestamp.unwrap_or_else(now));
+            state
+                .graph
+                .set_user_edge("likes", user_id, video_id, properties)
+                .await
+        }
+        Event::Unlike { .. } => {
+            state
+                .graph
+                .remove_user_edge("likes", user_id, video_id)
+                .await
+        }
+        Event::WatchProgress {
+            watch_percentage,
+            watch_time,
+            is_watched,
+            timestamp,
+            ..
+        } => {
+            if !(0.0..=1.0).contains(watch_percentage) {
+                return Err(AppError::BadRequest(format!(
+                    "watch_percentage must be between 0 and 1, got {}",
+                    watch_percentage
+                )));
+            }
+            let timestamp = timestamp.unwrap_or_else(now);
+            // Partial views are kept apart so that the video can be resumed
+            if !is_watched && *watch_percentage < WATCHED_PERCENTAGE {
+                let (properties, removed) =
+                    partial_view_properties(*watch_percentage, *watch_time, timestamp);
+                let change = state
+                    .graph
+                    .set_user_edge("in_progress", user_id, video_id, properties)
+                    .await?;
+                if !removed.is_empty() {
+                    state
+                        .graph
+                        .remove_user_edge_properties("in_progress", user_id, video_id, removed)
+                        .await?;
+                }
+                return Ok(change);
+            }
+            let properties = vec![
+                ("watchedPercentage", GValue::from(*watch_percentage)),
+                ("timestamp", GValue::from(timestamp)),
+            ];
+            // Each completed view is a new edge, so that repeat views count in the history
+            let change = state
+                .graph
+                .add_user_edge("watched", user_id, video_id, properties)
+                .await?;
+            state
+                .graph
+                .remove_user_edge("in_progress", user_id, video_id)
+                .await?;
+            Ok(change)
+        }
+        Event::NotInterested {
+            block_publisher,
+            timestamp,
+            ..
+        } => dismiss(state, user_id, video_id, *block_publisher, *timestamp).await,
+    }
+}
+
+pub async fn post_event(
+    State(state): State<AppState>,
+    body: Result<Json<Event>, JsonRejection>,
+) -> AppResult<Json<EventResult>> {
+    let Json(event) = body?;
+    println!("Event {:?}", event);
+    let change = apply_event(&state, event).await?;
+    reload_on_change(&state, change);
+    Ok(Json(EventResult { change }))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use gremlin_client::{List, Map};
+
+    use super::*;
+    use crate::{
+        context::{tests::video, Blocklist},
+        continue_watching::{rank_continue_watching, ContinueWatchingOrder},
+        graph::{parse_interaction, parse_partial_view},
+        source::shared_interactions,
+    };
+
+    // Properties of an edge, updated like `set_user_edge` and `remove_user_edge_properties` do
+    fn apply(
+        edge: &mut HashMap<&'static str, GValue>,
+        (properties, removed): (Vec<(&'static str, GValue)>, Vec<&'static str>),
+    ) {
+        edge.extend(properties);
+        for key in removed {
+            edge.remove(key);
+        }
+    }
+
+    fn fold(edge: &HashMap<&'static str, GValue>, key: &str) -> GValue {
+        // Optional properties are projected with `.values(key).fold()`
+        GValue::List(List::new(edge.get(key).cloned().into_iter().collect()))
+    }
+
+    #[test]
+    fn partial_view_without_time_forgets_the_earlier_one() {
+        let mut edge = HashMap::new();
+        apply(&mut edge, partial_view_properties(0.2, Some(60.0), 10));
+        apply(&mut edge, partial_view_properties(0.5, None, 20));
+        let projection = Map::from(HashMap::from([
+            ("user_id".to_string(), GValue::from("user")),
+            ("video_id".to_string(), GValue::from("a")),
+            ("watch_time".to_string(), fold(&edge, "watchTime")),
+            (
+                "watch_percentage".to_string(),
+                fold(&edge, "watchedPercentage"),
+            ),
+            ("timestamp".to_string(), fold(&edge, "timestamp")),
+        ]));
+        let partial_view = parse_partial_view(&projection).unwrap();
+        assert_eq!(partial_view.watch_time, None);
+        assert_eq!(partial_view.watch_percentage, 0.5);
+
+        let continue_watching = rank_continue_watching(
+            &[video("a", "publisher")],
+            &[],
+            &[partial_view],
+            &Blocklist::default(),
+            ContinueWatchingOrder::Recent,
+        );
+        assert_eq!(continue_watching[0].remaining_time, None);
+    }
+
+    #[test]
+    fn real_like_turns_a_seed_like_into_a_shared_one() {
+        // Like the picks of `POST /users/:id/interests`
+        let mut edge = HashMap::from([
+            ("timestamp", GValue::from(10_i64)),
+            ("seed", GValue::from(true)),
+        ]);
+        let project = |edge: &HashMap<&'static str, GValue>| {
+            Map::from(HashMap::from([
+                ("label".to_string(), GValue::from("likes")),
+                ("user_id".to_string(), GValue::from("user")),
+                ("video_id".to_string(), GValue::from("a")),
+                (
+                    "watch_percentage".to_string(),
+                    fold(edge, "watchedPercentage"),
+                ),
+                ("timestamp".to_string(), fold(edge, "timestamp")),
+                ("seed".to_string(), fold(edge, "seed")),
+            ]))
+        };
+        let seed_like = parse_interaction(&project(&edge)).unwrap();
+        assert!(shared_interactions(&[seed_like]).is_empty());
+
+        apply(&mut edge, (like_properties(20), Vec::new()));
+        let like = parse_interaction(&project(&edge)).unwrap();
+        assert_eq!(like.timestamp, Some(20));
+        assert_eq!(shared_interactions(&[like]).len(), 1);
+    }
+}
@@ -0,0 +1,335 @@
+use std::{
+    cmp::Reverse,
+    collections::{HashMap, HashSet},
+    str::FromStr,
+};
+
+use axum::{
+    extract::{
+        rejection::{PathRejection, QueryRejection},
+        Path, Query, State,
+    },
+    Json,
+};
+use serde::Serialize;
+use uuid::Uuid;
+
+use crate::{
+    context::Blocklist,
+    continue_watching::{rank_continue_watching, ContinueWatchingOrder},
+    error::{AppError, AppResult},
+    models::{
+        get_limit, split_list, FeedParams, Interaction, InteractionKind, PartialView, VideoMeta,
+        VideoRecommendation,
+    },
+    popularity::{now, rank_videos, trending_scores, TRENDING_HALF_LIFE_HOURS},
+    similar::rank_similar_videos,
+    source::DataSource,
+    state::AppState,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ShelfKind {
+    // Videos similar to each of the last videos of the history
+    BecauseYouWatched,
+    // Videos with the most recent interactions
+    Trending,
+    // Newest videos of the publishers the user liked or watched
+    NewFromPublishers,
+    // Videos the user started without finishing them
+    ContinueWatching,
+}
+
+impl ShelfKind {
+    pub fn name(&self) -> &'static str {
+        match self {
+            ShelfKind::BecauseYouWatched => "because_you_watched",
+            ShelfKind::Trending => "trending",
+            ShelfKind::NewFromPublishers => "new_from_publishers",
+            ShelfKind::ContinueWatching => "continue_watching",
+        }
+    }
+}
+
+impl FromStr for ShelfKind {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "because_you_watched" => Ok(ShelfKind::BecauseYouWatched),
+            "trending" => Ok(ShelfKind::Trending),
+            "new_from_publishers" => Ok(ShelfKind::NewFromPublishers),
+            "continue_watching" => Ok(ShelfKind::ContinueWatching),
+            _ => Err(format!("Unknown shelf: {}", value)),
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct Shelf {
+    pub kind: &'static str,
+    pub title: String,
+    pub videos: Vec<VideoRecommendation>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Feed {
+    pub shelves: Vec<Shelf>,
+}
+
+// Everything the shelves are built from, loaded once for the whole feed
+struct FeedData<'a> {
+    videos: &'a [VideoMeta],
+    history: Vec<Interaction>,
+    blocklist: Blocklist,
+    interactions: &'a [Interaction],
+    partial_views: Vec<PartialView>,
+    // `similar_to` neighbors of the recent videos of the history
+    similarities: HashMap<String, HashMap<String, f64>>,
+}
+
+impl FeedData<'_> {
+    fn is_seen(&self, video_id: &str) -> bool {
+        self.history
+            .iter()
+            .any(|interaction| interaction.video_id == video_id)
+    }
+
+    fn recent_videos(&self, count: usize) -> Vec<&Interaction> {
+        // Last liked or watched videos, most recent first, interactions without a timestamp last
+        let mut history: Vec<&Interaction> = self.history.iter().collect();
+        history.sort_by_key(|interaction| Reverse(interaction.timestamp));
+        let mut seen = HashSet::new();
+        history
+            .into_iter()
+            .filter(|interaction| !self.blocklist.videos.contains(&interaction.video_id))
+            .filter(|interaction| seen.insert(interaction.video_id.as_str()))
+            .take(count)
+            .collect()
+    }
+}
+
+async fn load_feed_data(
+    data: &DataSource,
+    user_id: Uuid,
+    recent_videos: usize,
+) -> AppResult<FeedData<'_>> {
+    data.check_user(user_id).await?;
+    let mut feed_data = FeedData {
+        videos: data.get_videos().await?,
+        history: data.get_user_history(user_id).await?,
+        blocklist: Blocklist::new(&data.get_user_dismissals(user_id).await?),
+        interactions: data.get_interactions().await?,
+        partial_views: data.get_user_partial_views(user_id).await?,
+        similarities: HashMap::new(),
+    };
+
+    let recent_video_ids: Vec<String> = feed_data
+        .recent_videos(recent_videos)
+        .into_iter()
+        .map(|interaction| interaction.video_id.clone())
+        .collect();
+    for video_id in recent_video_ids {
+        let similarities = data.get_similar_videos(&video_id).await?;
+        feed_data.similarities.insert(video_id, similarities);
+    }
+    Ok(feed_data)
+}
+
+fn new_from_publishers(data: &FeedData) -> Vec<VideoRecommendation> {
+    // The graph has no follows nor publication dates: the publishers of the liked or watched
+    // videos are the ones followed, and a video is as new as its first like or watch,
+    // videos nobody interacted with yet being the newest. The score is the number of
+    // interactions of the user with the publisher.
+    let publisher_ids: HashMap<&str, &str> = data
+        .videos
+        .iter()
+        .map(|video| (video.video_id.as_str(), video.publisher_id.as_str()))
+        .collect();
+    let mut affinities: HashMap<&str, f64> = HashMap::new();
+    for interaction in data.history.iter() {
+        if let Some(publisher_id) = publisher_ids.get(interaction.video_id.as_str()) {
+            *affinities.entry(publisher_id).or_insert(0.0) += 1.0;
+        }
+    }
+    let mut first_seen: HashMap<&str, Option<i64>> = HashMap::new();
+    for interaction in data.interactions.iter() {
+        let entry = first_seen
+            .entry(interaction.video_id.as_str())
+            .or_insert(interaction.timestamp);
+        *entry = match (*entry, interaction.timestamp) {
+            (Some(first), Some(timestamp)) => Some(first.min(timestamp)),
+            (first, timestamp) => first.or(timestamp),
+        };
+    }
+
+    let mut videos: Vec<(i64, VideoRecommendation)> = data
+        .videos
+        .iter()
+        .filter_map(|video| {
+            let affinity = affinities.get(video.publisher_id.as_str())?;
+            let newness = match first_seen.get(video.video_id.as_str()) {
+                Some(first_seen) => first_seen.unwrap_or(0),
+                None => i64::MAX,
+            };
+            Some((
+                newness,
+                VideoRecommendation {
+                    id: video.video_id.clone(),
+                    title: video.title.clone(),
+                    publisher_id: video.publisher_id.clone(),
+                    score: *affinity as f32,
+                    contributions: None,
+                    reasons: None,
+                },
+            ))
+        })
+        .collect();
+    videos.sort_by(|(a_newness, a), (b_newness, b)| {
+        b_newness
+            .cmp(a_newness)
+            .then_with(|| b.score.total_cmp(&a.score))
+            .then_with(|| a.id.cmp(&b.id))
+    });
+    videos.into_iter().map(|(_, video)| video).collect()
+}
+
+fn continue_watching(data: &FeedData) -> Vec<VideoRecommendation> {
+    // Videos started and not finished, most recent first, the score is the watched percentage
+    rank_continue_watching(
+        data.videos,
+        &data.history,
+        &data.partial_views,
+        &data.blocklist,
+        ContinueWatchingOrder::Recent,
+    )
+    .into_iter()
+    .map(|video| VideoRecommendation {
+        id: video.id,
+        title: video.title,
+        publisher_id: video.publisher_id,
+        score: video.watch_percentage as f32,
+        contributions: None,
+        reasons: None,
+    })
+    .collect()
+}
+
+fn fill(
+    data: &FeedData,
+    placed: &mut HashSet<String>,
+    candidates: Vec<VideoRecommendation>,
+    limit: usize,
+    unseen_only: bool,
+) -> Vec<VideoRecommendation> {
+    // Videos already on another shelf, dismissed or from a blocked publisher are skipped
+    let videos: Vec<VideoRecommendation> = candidates
+        .into_iter()
+        .filter(|video| !placed.contains(&video.id) && !data.blocklist.blocks(video))
+        .filter(|video| !unseen_only || !data.is_seen(&video.id))
+        .take(limit)
+        .collect();
+    placed.extend(videos.iter().map(|video| video.id.clone()));
+    videos
+}
+
+fn build_feed(
+    data: &FeedData,
+    shelves: &[ShelfKind],
+    limit: usize,
+    recent_videos: usize,
+) -> AppResult<Feed> {
+    // Shelves are filled in order, so a video shows up on the first shelf it fits in
+    let mut placed = HashSet::new();
+    let mut feed = Feed {
+        shelves: Vec::new(),
+    };
+    for kind in shelves {
+        let mut shelf = |title: String, candidates: Vec<VideoRecommendation>, unseen_only: bool| {
+            let videos = fill(data, &mut placed, candidates, limit, unseen_only);
+            if !videos.is_empty() {
+                feed.shelves.push(Shelf {
+                    kind: kind.name(),
+                    title,
+                    videos,
+                });
+            }
+        };
+        match kind {
+            ShelfKind::BecauseYouWatched => {
+                for interaction in data.recent_videos(recent_videos) {
+                    let video_id = &interaction.video_id;
+                    let Some(video) = data.videos.iter().find(|video| &video.video_id == video_id)
+                    else {
+                        continue;
+                    };
+                    let verb = match interaction.kind {
+                        InteractionKind::Like => "liked",
+                        InteractionKind::Watch => "watched",
+                    };
+                    let candidates = rank_similar_videos(
+                        video_id,
+                        data.videos,
+                        data.similarities.get(video_id),
+                        0.0,
+                    )?;
+                    shelf(
+                        format!("Because you {} {}", verb, video.title),
+                        candidates,
+                        true,
+                    );
+                }
+            }
+            ShelfKind::Trending => {
+                let mut candidates = rank_videos(
+                    data.videos,
+                    &trending_scores(data.interactions, now(), TRENDING_HALF_LIFE_HOURS),
+                );
+                candidates.retain(|video| video.score > 0.0);
+                shelf("Trending".to_string(), candidates, true);
+            }
+            ShelfKind::NewFromPublishers => {
+                shelf(
+                    "New from publishers you follow".to_string(),
+                    new_from_publishers(data),
+                    true,
+                );
+            }
+            ShelfKind::ContinueWatching => {
+                shelf(
+                    "Continue watching".to_string(),
+                    continue_watching(data),
+                    false,
+                );
+            }
+        }
+    }
+    Ok(feed)
+}
+
+pub async fn get_user_feed(
+    State(state): State<AppState>,
+    path: Result<Path<Uuid>, PathRejection>,
+    query: Result<Query<FeedParams>, QueryRejection>,
+) -> AppResult<Json<Feed>> {
+    let Path(user_id) = path?;
+    let Query(query_params) = query?;
+    // `limit` is the size of each shelf
+    let limit = get_limit(query_params.limit.or(Some(state.config.feed_shelf_size)))?;
+    let shelves = match &query_params.shelves {
+        Some(_) => split_list(&query_params.shelves)
+            .iter()
+            .map(|name| name.parse().map_err(AppError::BadRequest))
+            .collect::<AppResult<Vec<ShelfKind>>>()?,
+        None => state.config.feed_shelves.clone(),
+    };
+
+    let source = state.data();
+    let data = load_feed_data(&source, user_id, state.config.feed_recent_videos).await?;
+    Ok(Json(build_feed(
+        &data,
+        &shelves,
+        limit,
+        state.config.feed_recent_videos,
+    )?))
+}
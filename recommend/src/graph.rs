@@ -0,0 +1,623 @@
+use gremlin_client::{
+    aio::{AsyncTerminator, GremlinClient},
+    process::traversal::{traversal, GraphTraversal, GraphTraversalSource, __},
+    structure::{P, T},
+    ConnectionOptions, Edge, GKey, GValue, GremlinError, GremlinResult, List, Map, Vertex,
+};
+use std::{
+    collections::{HashMap, HashSet},
+    future::Future,
+    time::Duration,
+};
+
+use serde::Serialize;
+use uuid::Uuid;
+
+use crate::{
+    config::Config,
+    error::{AppError, AppResult},
+    models::{Dismissal, Interaction, InteractionKind, PartialView, Similarities, VideoMeta},
+};
+
+#[derive(Debug, Clone, Copy, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EdgeChange {
+    Added,
+    Updated,
+    Removed,
+    Unchanged,
+}
+
+// Traversal source over a pool of connections, cheap to clone and shared by every request
+#[derive(Clone)]
+pub struct Graph {
+    g: GraphTraversalSource<AsyncTerminator>,
+    timeout: Duration,
+}
+
+pub fn as_f64(value: &GValue) -> Option<f64> {
+    // Numeric properties come back as Float (f32) or Double (f64) depending on how they were stored
+    match value {
+        GValue::Double(value) => Some(*value),
+        GValue::Float(value) => Some(*value as f64),
+        GValue::Int64(value) => Some(*value as f64),
+        GValue::Int32(value) => Some(*value as f64),
+        _ => None,
+    }
+}
+
+fn to_app_error(err: GremlinError) -> AppError {
+    // Only the connection failures mean the graph is unavailable, the other errors come from the query
+    match err {
+        GremlinError::Generic(_)
+        | GremlinError::WebSocket(_)
+        | GremlinError::WebSocketAsync(_)
+        | GremlinError::Pool(_)
+        | GremlinError::ChannelSend(_) => AppError::GraphUnavailable(err.to_string()),
+        _ => AppError::GraphQuery(err.to_string()),
+    }
+}
+
+fn get_string(map: &Map, key: &str) -> Option<String> {
+    map.get(key)?.get::<String>().ok().cloned()
+}
+
+fn get_optional(map: &Map, key: &str) -> Option<GValue> {
+    // Optional properties are projected with `.values(key).fold()`, so they come back as a list
+    map.get(key)?.get::<List>().ok()?.iter().next().cloned()
+}
+
+impl Graph {
+    pub async fn connect(config: &Config) -> Graph {
+        // Connections are opened lazily by the pool, up to `graph_pool_size`
+        let options = ConnectionOptions::builder()
+            .host(config.graph_host.clone())
+            .port(config.graph_port)
+            .pool_size(config.graph_pool_size)
+            .build();
+        let client = GremlinClient::connect(options)
+            .await
+            .expect("Failed to connect to database");
+        Graph {
+            g: traversal().with_remote_async(client),
+            timeout: Duration::from_secs(config.graph_timeout_seconds),
+        }
+    }
+
+    async fn run<R>(&self, query: impl Future<Output = GremlinResult<R>>) -> AppResult<R> {
+        // Waiting for a connection of the pool counts in the timeout too
+        match tokio::time::timeout(self.timeout, query).await {
+            Ok(result) => result.map_err(to_app_error),
+            Err(_) => Err(AppError::GraphUnavailable("query timed out".to_string())),
+        }
+    }
+
+    pub async fn get_videos(&self) -> AppResult<Vec<VideoMeta>> {
+        // Get id, title and publisher of every video in one query
+        let query = self
+            .g
+            .v(())
+            .has_label("video")
+            .project(["video_id", "title", "publisher_id"])
+            .by("video_id")
+            .by("title")
+            .by("publisher_id")
+            .to_list();
+        let values = self.run(query).await?;
+        Ok(values
+            .iter()
+            .filter_map(|value| {
+                let map = value.get::<Map>().ok()?;
+                Some(VideoMeta {
+                    video_id: get_string(map, "video_id")?,
+                    title: get_string(map, "title")?,
+                    publisher_id: get_string(map, "publisher_id")?,
+                })
+            })
+            .collect())
+    }
+
+    pub async fn get_publishers(&self) -> AppResult<HashMap<String, Vec<String>>> {
+        // Catalog of each publisher, from the `published` edges
+        let query = self
+            .g
+            .v(())
+            .has_label("publisher")
+            .project(["publisher_id", "video_ids"])
+            .by("publisher_id")
+            .by(__.out("published").values("video_id").fold())
+            .to_list();
+        let values = self.run(query).await?;
+        Ok(values
+            .iter()
+            .filter_map(|value| {
+                let map = value.get::<Map>().ok()?;
+                let video_ids = map
+                    .get("video_ids")?
+                    .get::<List>()
+                    .ok()?
+                    .iter()
+                    .filter_map(|video_id| video_id.get::<String>().ok().cloned())
+                    .collect();
+                Some((get_string(map, "publisher_id")?, video_ids))
+            })
+            .collect())
+    }
+
+    pub async fn user_exists(&self, user_id: Uuid) -> AppResult<bool> {
+        let query = self
+            .g
+            .v(())
+            .has(("user", "user_id", user_id.to_string()))
+            .has_next();
+        self.run(query).await
+    }
+
+    pub async fn add_user(&self, user_id: Uuid) -> AppResult<()> {
+        let query = self
+            .g
+            .add_v("user")
+            .property("user_id", user_id.to_string())
+            .next();
+        self.run(query).await?;
+        Ok(())
+    }
+
+    pub async fn video_exists(&self, video_id: &str) -> AppResult<bool> {
+        let query = self
+            .g
+            .v(())
+            .has(("video", "video_id", video_id.to_owned()))
+            .has_next();
+        self.run(query).await
+    }
+
+    async fn get_vertex(&self, label: &str, key: &str, id: String) -> AppResult<Option<Vertex>> {
+        let query = self.g.v(()).has((label, key, id)).next();
+        self.run(query).await
+    }
+
+    fn user_edges(
+        &self,
+        label: &str,
+        user_id: Uuid,
+        video_id: &str,
+    ) -> GraphTraversal<Vertex, Edge, AsyncTerminator> {
+        // Edges of the given label going from the user to the video
+        self.g
+            .v(())
+            .has(("user", "user_id", user_id.to_string()))
+            .out_e(label)
+            .where_(__.in_v().has(("video", "video_id", video_id.to_owned())))
+    }
+
+    pub async fn has_user_edge(
+        &self,
+        label: &str,
+        user_id: Uuid,
+        video_id: &str,
+    ) -> AppResult<bool> {
+        let query = self.user_edges(label, user_id, video_id).has_next();
+        self.run(query).await
+    }
+
+    pub async fn set_user_edge(
+        &self,
+        label: &str,
+        user_id: Uuid,
+        video_id: &str,
+        properties: Vec<(&str, GValue)>,
+    ) -> AppResult<EdgeChange> {
+        // Update the properties of the edge from the user to the video, or add it when there is none
+        let mut update = self.user_edges(label, user_id, video_id);
+        for (key, value) in properties.iter() {
+            update = update.property(key, value.clone());
+        }
+        if !self.run(update.to_list()).await?.is_empty() {
+            return Ok(EdgeChange::Updated);
+        }
+        self.add_user_edge(label, user_id, video_id, properties)
+            .await
+    }
+
+    pub async fn add_user_edge(
+        &self,
+        label: &str,
+        user_id: Uuid,
+        video_id: &str,
+        properties: Vec<(&str, GValue)>,
+    ) -> AppResult<EdgeChange> {
+        // Add an edge from the user to the video, next to the ones already there
+        let user = self
+            .get_vertex("user", "user_id", user_id.to_string())
+            .await?
+            .ok_or(AppError::UnknownUser(user_id))?;
+        let video = self
+            .get_vertex("video", "video_id", video_id.to_owned())
+            .await?
+            .ok_or_else(|| AppError::UnknownVideo(video_id.to_owned()))?;
+        let mut edge = self.g.add_e(label).from(&user).to(&video);
+        for (key, value) in properties {
+            edge = edge.property(key, value);
+        }
+        self.run(edge.next()).await?;
+        Ok(EdgeChange::Added)
+    }
+
+    pub async fn remove_user_edge(
+        &self,
+        label: &str,
+        user_id: Uuid,
+        video_id: &str,
+    ) -> AppResult<EdgeChange> {
+        if !self
+            .run(self.user_edges(label, user_id, video_id).has_next())
+            .await?
+        {
+            return Ok(EdgeChange::Unchanged);
+        }
+        self.run(self.user_edges(label, user_id, video_id).drop().to_list())
+            .await?;
+        Ok(EdgeChange::Removed)
+    }
+
+    pub async fn remove_user_edge_properties(
+        &self,
+        label: &str,
+        user_id: Uuid,
+        video_id: &str,
+        keys: Vec<&str>,
+    ) -> AppResult<()> {
+        // Remove the given properties from the edge from the user to the video, if they are set
+        self.run(
+            self.user_edges(label, user_id, video_id)
+                .properties(keys)
+                .drop()
+                .to_list(),
+        )
+        .await?;
+        Ok(())
+    }
+
+    pub async fn get_user_ids(&self) -> AppResult<HashSet<String>> {
+        let query = self.g.v(()).has_label("user").values("user_id").to_list();
+        let values = self.run(query).await?;
+        Ok(values
+            .into_iter()
+            .filter_map(|value| value.take::<String>().ok())
+            .collect())
+    }
+
+    async fn project_interactions<S>(
+        &self,
+        edges: GraphTraversal<S, Edge, AsyncTerminator>,
+    ) -> AppResult<Vec<Interaction>> {
+        // Flatten `likes` and `watched` edges into interactions, resolving both ends to their ids
+        let query = edges
+            .project([
+                "label",
+                "user_id",
+                "video_id",
+                "watch_percentage",
+                "timestamp",
+                "seed",
+            ])
+            .by(T::Label)
+            .by(__.out_v().values("user_id"))
+            .by(__.in_v().values("video_id"))
+            .by(__.values("watchedPercentage").fold())
+            .by(__.values("timestamp").fold())
+            .by(__.values("seed").fold())
+            .to_list();
+        let values = self.run(query).await?;
+        Ok(values
+            .iter()
+            .filter_map(|value| parse_interaction(value.get::<Map>().ok()?))
+            .collect())
+    }
+
+    pub async fn get_interactions(&self) -> AppResult<Vec<Interaction>> {
+        // Get every like and completed watch in the graph
+        self.project_interactions(self.g.e(()).has_label(vec!["likes", "watched"]))
+            .await
+    }
+
+    pub async fn get_user_history(&self, user_id: Uuid) -> AppResult<Vec<Interaction>> {
+        // Get every like and completed watch of the user, repeat views included
+        self.project_interactions(
+            self.g
+                .v(())
+                .has(("user", "user_id", user_id.to_string()))
+                .out_e(vec!["likes", "watched"]),
+        )
+        .await
+    }
+
+    async fn project_partial_views<S>(
+        &self,
+        edges: GraphTraversal<S, Edge, AsyncTerminator>,
+    ) -> AppResult<Vec<PartialView>> {
+        // Flatten `in_progress` edges, resolving both ends to their ids
+        let query = edges
+            .project([
+                "user_id",
+                "video_id",
+                "watch_time",
+                "watch_percentage",
+                "timestamp",
+            ])
+            .by(__.out_v().values("user_id"))
+            .by(__.in_v().values("video_id"))
+            .by(__.values("watchTime").fold())
+            .by(__.values("watchedPercentage").fold())
+            .by(__.values("timestamp").fold())
+            .to_list();
+        let values = self.run(query).await?;
+        Ok(values
+            .iter()
+            .filter_map(|value| parse_partial_view(value.get::<Map>().ok()?))
+            .collect())
+    }
+
+    pub async fn get_partial_views(&self) -> AppResult<Vec<PartialView>> {
+        self.project_partial_views(self.g.e(()).has_label("in_progress"))
+            .await
+    }
+
+    pub async fn get_user_partial_views(&self, user_id: Uuid) -> AppResult<Vec<PartialView>> {
+        self.project_partial_views(
+            self.g
+                .v(())
+                .has(("user", "user_id", user_id.to_string()))
+                .out_e("in_progress"),
+        )
+        .await
+    }
+
+    async fn project_dismissals<S>(
+        &self,
+        edges: GraphTraversal<S, Edge, AsyncTerminator>,
+    ) -> AppResult<Vec<Dismissal>> {
+        // Flatten `dismissed` edges, resolving both ends to their ids
+        let query = edges
+            .project([
+                "user_id",
+                "video_id",
+                "publisher_id",
+                "block_publisher",
+                "timestamp",
+            ])
+            .by(__.out_v().values("user_id"))
+            .by(__.in_v().values("video_id"))
+            .by(__.in_v().values("publisher_id"))
+            .by(__.values("blockPublisher").fold())
+            .by(__.values("timestamp").fold())
+            .to_list();
+        let values = self.run(query).await?;
+        Ok(values
+            .iter()
+            .filter_map(|value| {
+                let map = value.get::<Map>().ok()?;
+                Some(Dismissal {
+                    user_id: get_string(map, "user_id")?,
+                    video_id: get_string(map, "video_id")?,
+                    publisher_id: get_string(map, "publisher_id")?,
+                    block_publisher: get_optional(map, "block_publisher")
+                        .and_then(|value| value.take::<bool>().ok())
+                        .unwrap_or(false),
+                    timestamp: get_optional(map, "timestamp")
+                        .and_then(|value| value.take::<i64>().ok()),
+                })
+            })
+            .collect())
+    }
+
+    pub async fn get_dismissals(&self) -> AppResult<Vec<Dismissal>> {
+        self.project_dismissals(self.g.e(()).has_label("dismissed"))
+            .await
+    }
+
+    pub async fn get_user_dismissals(&self, user_id: Uuid) -> AppResult<Vec<Dismissal>> {
+        self.project_dismissals(
+            self.g
+                .v(())
+                .has(("user", "user_id", user_id.to_string()))
+                .out_e("dismissed"),
+        )
+        .await
+    }
+
+    async fn project_similarities<S>(
+        &self,
+        edges: GraphTraversal<S, Edge, AsyncTerminator>,
+    ) -> AppResult<Similarities> {
+        // Index `similar_to` edges by the video they start from
+        let query = edges
+            .project(["from", "to", "similarity"])
+            .by(__.out_v().values("video_id"))
+            .by(__.in_v().values("video_id"))
+            .by("similarity")
+            .to_list();
+        let edges = self.run(query).await?;
+
+        let mut similarities = Similarities::new();
+        for edge in edges.iter() {
+            let Ok(map) = edge.get::<Map>() else {
+                continue;
+            };
+            if let (Some(from), Some(to), Some(similarity)) = (
+                get_string(map, "from"),
+                get_string(map, "to"),
+                map.get("similarity").and_then(as_f64),
+            ) {
+                similarities.entry(from).or_default().insert(to, similarity);
+            }
+        }
+        Ok(similarities)
+    }
+
+    pub async fn get_similarities_between(&self, video_ids: &[String]) -> AppResult<Similarities> {
+        // Get the `similar_to` edges linking the given videos together in one query
+        self.project_similarities(
+            self.g
+                .v(())
+                .has(("video", "video_id", P::within(video_ids.to_vec())))
+                .out_e("similar_to")
+                .where_(__.in_v().has(("video_id", P::within(video_ids.to_vec())))),
+        )
+        .await
+    }
+
+    pub async fn get_similar_videos(&self, video_id: &str) -> AppResult<HashMap<String, f64>> {
+        // Get the `similar_to` edges going from the video
+        let mut similarities = self
+            .project_similarities(
+                self.g
+                    .v(())
+                    .has(("video", "video_id", video_id.to_owned()))
+                    .out_e("similar_to"),
+            )
+            .await?;
+        Ok(similarities.remove(video_id).unwrap_or_default())
+    }
+
+    pub async fn get_all_similarities(&self) -> AppResult<Similarities> {
+        // Get every `similar_to` edge of the graph
+        self.project_similarities(self.g.e(()).has_label("similar_to"))
+            .await
+    }
+
+    pub async fn get_content_similarities(
+        &self,
+        source_video_ids: &[String],
+        not_seen_video_ids: &[String],
+    ) -> AppResult<HashMap<String, Vec<(String, f64)>>> {
+        // In one traversal, follow the `similar_to` edges going from the given videos of the
+        // history and the dismissed ones to the videos not seen, grouped by video not seen.
+        // The scores are not aggregated here: they depend on the weight of each video of the
+        // history and on the aggregation of the request, and the titles come from the catalog.
+        if source_video_ids.is_empty() || not_seen_video_ids.is_empty() {
+            return Ok(HashMap::new());
+        }
+        let query = self
+            .g
+            .v(())
+            .has(("video", "video_id", P::within(source_video_ids.to_vec())))
+            .out_e("similar_to")
+            .where_(
+                __.in_v()
+                    .has(("video_id", P::within(not_seen_video_ids.to_vec()))),
+            )
+            .group()
+            .by(__.in_v().values("video_id"))
+            .by(__
+                .project(["from", "similarity"])
+                .by(__.out_v().values("video_id"))
+                .by("similarity")
+                .fold())
+            .to_list();
+        Ok(parse_content_similarities(&self.run(query).await?))
+    }
+}
+
+pub fn parse_interaction(map: &Map) -> Option<Interaction> {
+    let kind = match get_string(map, "label")?.as_str() {
+        "likes" => InteractionKind::Like,
+        "watched" => InteractionKind::Watch,
+        _ => return None,
+    };
+    Some(Interaction {
+        user_id: get_string(map, "user_id")?,
+        video_id: get_string(map, "video_id")?,
+        kind,
+        watch_percentage: get_optional(map, "watch_percentage")
+            .as_ref()
+            .and_then(as_f64),
+        timestamp: get_optional(map, "timestamp").and_then(|value| value.take::<i64>().ok()),
+        seed: get_optional(map, "seed")
+            .and_then(|value| value.take::<bool>().ok())
+            .unwrap_or(false),
+    })
+}
+
+pub fn parse_partial_view(map: &Map) -> Option<PartialView> {
+    Some(PartialView {
+        user_id: get_string(map, "user_id")?,
+        video_id: get_string(map, "video_id")?,
+        watch_time: get_optional(map, "watch_time").as_ref().and_then(as_f64),
+        watch_percentage: get_optional(map, "watch_percentage")
+            .as_ref()
+            .and_then(as_f64)
+            .unwrap_or(0.0),
+        timestamp: get_optional(map, "timestamp").and_then(|value| value.take::<i64>().ok()),
+    })
+}
+
+fn parse_content_similarities(groups: &[Map]) -> HashMap<String, Vec<(String, f64)>> {
+    // The traversal gives one map from each unseen video to the edges leading to it
+    let mut similarities: HashMap<String, Vec<(String, f64)>> = HashMap::new();
+    for (not_watched_video_id, edges) in groups.iter().flat_map(|group| group.iter()) {
+        let (GKey::String(not_watched_video_id), Ok(edges)) =
+            (not_watched_video_id, edges.get::<List>())
+        else {
+            continue;
+        };
+        let edges = edges.iter().filter_map(|edge| {
+            let map = edge.get::<Map>().ok()?;
+            Some((
+                get_string(map, "from")?,
+                map.get("similarity").and_then(as_f64)?,
+            ))
+        });
+        similarities
+            .entry(not_watched_video_id.clone())
+            .or_default()
+            .extend(edges);
+    }
+    similarities
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::source::content_similarities;
+
+    fn edge(from: &str, similarity: f64) -> GValue {
+        GValue::from(HashMap::from([
+            ("from".to_string(), GValue::from(from)),
+            ("similarity".to_string(), GValue::from(similarity)),
+        ]))
+    }
+
+    #[test]
+    fn grouped_similarities_match_the_snapshot() {
+        // What the traversal returns for a user who consumed `a` and `b`
+        let group = Map::from(HashMap::from([
+            (
+                GKey::from("c"),
+                GValue::from(vec![edge("a", 0.8), edge("b", 0.4)]),
+            ),
+            (GKey::from("d"), GValue::from(vec![edge("a", 0.5)])),
+        ]));
+        let mut grouped = parse_content_similarities(&[group]);
+
+        let mut similarities = Similarities::new();
+        for (from, to, similarity) in [("a", "c", 0.8), ("b", "c", 0.4), ("a", "d", 0.5)] {
+            similarities
+                .entry(from.to_string())
+                .or_default()
+                .insert(to.to_string(), similarity);
+        }
+        let source_video_ids = vec!["a".to_string(), "b".to_string()];
+        let not_seen_video_ids = vec!["c".to_string(), "d".to_string(), "e".to_string()];
+        let mut expected =
+            content_similarities(&similarities, &source_video_ids, &not_seen_video_ids);
+        // Videos without any edge are only left out by the traversal
+        expected.retain(|_, edges| !edges.is_empty());
+
+        for edges in grouped.values_mut().chain(expected.values_mut()) {
+            edges.sort_by(|a, b| a.0.cmp(&b.0));
+        }
+        assert_eq!(grouped, expected);
+    }
+}
@@ -0,0 +1,195 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    str::FromStr,
+};
+
+use crate::{
+    config::Config,
+    error::{AppError, AppResult},
+    models::{Params, VideoMeta, VideoRecommendation},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Strategy {
+    // Similarity of the video descriptions (`similar_to` edges)
+    Content,
+    // Videos consumed by the same users (item-based collaborative filtering)
+    Collaborative,
+    // Number of likes and completed watches
+    Popularity,
+}
+
+impl Strategy {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Strategy::Content => "content",
+            Strategy::Collaborative => "collaborative",
+            Strategy::Popularity => "popularity",
+        }
+    }
+}
+
+impl FromStr for Strategy {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "content" => Ok(Strategy::Content),
+            "collaborative" => Ok(Strategy::Collaborative),
+            "popularity" => Ok(Strategy::Popularity),
+            _ => Err(format!("Unknown strategy: {}", value)),
+        }
+    }
+}
+
+pub type BlendWeights = BTreeMap<Strategy, f64>;
+
+pub fn parse_weights(value: &str) -> Result<BlendWeights, String> {
+    // Parse weights written as `content:0.6,collaborative:0.3,popularity:0.1`
+    let mut weights = BlendWeights::new();
+    for pair in value.split(',').filter(|pair| !pair.trim().is_empty()) {
+        let (strategy, weight) = pair
+            .split_once(':')
+            .ok_or_else(|| format!("Invalid strategy weight: {}", pair))?;
+        let weight: f64 = weight
+            .trim()
+            .parse()
+            .map_err(|_| format!("Invalid weight for {}: {}", strategy, weight))?;
+        if weight < 0.0 {
+            return Err(format!("Negative weight for {}", strategy));
+        }
+        weights.insert(strategy.trim().parse()?, weight);
+    }
+    if weights.values().sum::<f64>() <= 0.0 {
+        return Err("At least one strategy needs a positive weight".to_string());
+    }
+    Ok(weights)
+}
+
+pub fn get_blend_weights(config: &Config, query_params: &Params) -> AppResult<BlendWeights> {
+    // Weights given in the request win over the profile
+    if let Some(weights) = &query_params.weights {
+        return parse_weights(weights)
+            .map_err(|err| AppError::BadRequest(format!("Invalid weights {}: {}", weights, err)));
+    }
+    let profile = query_params
+        .profile
+        .as_ref()
+        .unwrap_or(&config.blend_profile);
+    config.blend_profiles.get(profile).cloned().ok_or_else(|| {
+        let mut profiles: Vec<&str> = config.blend_profiles.keys().map(String::as_str).collect();
+        profiles.sort();
+        AppError::BadRequest(format!(
+            "Unknown profile {}, expected one of {}",
+            profile,
+            profiles.join(", ")
+        ))
+    })
+}
+
+pub fn normalize(scores: &HashMap<String, f64>) -> HashMap<String, f64> {
+    // Min-max normalization so that strategies with different scales can be added up,
+    // when every video has the same score they all get 1
+    let min = scores.values().cloned().fold(f64::INFINITY, f64::min);
+    let max = scores.values().cloned().fold(f64::NEG_INFINITY, f64::max);
+    scores
+        .iter()
+        .map(|(video_id, score)| {
+            let normalized = if max > min {
+                (score - min) / (max - min)
+            } else {
+                1.0
+            };
+            (video_id.clone(), normalized)
+        })
+        .collect()
+}
+
+pub fn blend(
+    candidates: &[VideoMeta],
+    strategy_scores: &[(Strategy, HashMap<String, f64>)],
+    weights: &BlendWeights,
+) -> Vec<VideoRecommendation> {
+    // Weighted sum of the normalized scores of each strategy, the weights are scaled to sum to 1.
+    // Candidates no strategy could score are left out.
+    let total_weight: f64 = weights.values().sum();
+    let normalized: Vec<(Strategy, f64, HashMap<String, f64>)> = strategy_scores
+        .iter()
+        .filter_map(|(strategy, scores)| {
+            let weight = weights.get(strategy).copied().unwrap_or(0.0) / total_weight;
+            (weight > 0.0).then(|| (*strategy, weight, normalize(scores)))
+        })
+        .collect();
+
+    let mut recommendations: Vec<VideoRecommendation> = candidates
+        .iter()
+        .filter_map(|video| {
+            let mut contributions = BTreeMap::new();
+            for (strategy, weight, scores) in normalized.iter() {
+                if let Some(score) = scores.get(&video.video_id) {
+                    contributions.insert(strategy.name().to_string(), (weight * score) as f32);
+                }
+            }
+            if contributions.is_empty() {
+                return None;
+            }
+            Some(VideoRecommendation {
+                id: video.video_id.clone(),
+                title: video.title.clone(),
+                publisher_id: video.publisher_id.clone(),
+                score: contributions.values().sum(),
+                contributions: Some(contributions),
+                reasons: None,
+            })
+        })
+        .collect();
+    recommendations.sort_by(|a, b| b.score.total_cmp(&a.score).then_with(|| a.id.cmp(&b.id)));
+    recommendations
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::context::tests::video;
+
+    fn scores(pairs: &[(&str, f64)]) -> HashMap<String, f64> {
+        pairs
+            .iter()
+            .map(|(video_id, score)| (video_id.to_string(), *score))
+            .collect()
+    }
+
+    #[test]
+    fn blend_adds_up_the_normalized_scores() {
+        let candidates = vec![
+            video("a", "publisher"),
+            video("b", "publisher"),
+            video("c", "publisher"),
+            video("d", "publisher"),
+        ];
+        let strategy_scores = vec![
+            (
+                Strategy::Content,
+                scores(&[("a", 0.2), ("b", 0.6), ("c", 1.0)]),
+            ),
+            (Strategy::Popularity, scores(&[("a", 10.0), ("b", 0.0)])),
+        ];
+        // Weights are scaled to sum to 1
+        let weights = BlendWeights::from([(Strategy::Content, 3.0), (Strategy::Popularity, 1.0)]);
+        let recommendations = blend(&candidates, &strategy_scores, &weights);
+
+        let ranking: Vec<(&str, f32)> = recommendations
+            .iter()
+            .map(|recommendation| (recommendation.id.as_str(), recommendation.score))
+            .collect();
+        // `d` has no score at all
+        assert_eq!(ranking, vec![("c", 0.75), ("b", 0.375), ("a", 0.25)]);
+        assert_eq!(
+            recommendations[2].contributions,
+            Some(BTreeMap::from([
+                ("content".to_string(), 0.0),
+                ("popularity".to_string(), 0.25)
+            ]))
+        );
+    }
+}
@@ -1,169 +1,104 @@
+mod audience;
+mod collaborative;
+mod config;
+mod context;
+mod continue_watching;
+mod dismissals;
+mod error;
+mod events;
+mod feed;
+mod graph;
+mod hybrid;
+mod models;
+mod onboarding;
+mod pipeline;
+mod playlists;
+mod popularity;
+mod publishers;
+mod recommendations;
+mod rerank;
+mod scoring;
+mod similar;
+mod similar_users;
+mod snapshot;
+mod source;
+mod state;
+
+use std::time::Duration;
+
 use axum::{
-    extract::{Path, Query},
-    routing::get,
+    routing::{delete, get, post},
     Router,
 };
-use gremlin_client::{
-    process::traversal::{traversal, GraphTraversalSource, SyncTerminator, __},
-    GremlinClient, Vertex,
-};
-use serde::{Deserialize, Serialize};
-use uuid::Uuid;
-
-#[derive(Debug, Deserialize)]
-struct Params {
-    limit: Option<i32>,
-}
-
-#[derive(Serialize, Deserialize, Debug)]
-struct VideoRecommendation {
-    id: String,
-    title: String,
-    score: f32,
-}
-
-fn get_transversal() -> GraphTraversalSource<SyncTerminator> {
-    // Get transversal
-    let client = GremlinClient::connect("localhost").expect("Failed to connect to database");
-    let g = traversal().with_remote(client);
-    g
-}
-
-fn get_videos_watched_by_user_id(user_id: Uuid) -> Vec<gremlin_client::Vertex> {
-    // Get videos seen and liked by user
-    let g = get_transversal();
-
-    // For the user with user_id, get the videos they have seen and liked, and dedup
-    g.v(())
-        .has(("user", "user_id", user_id.to_string()))
-        .out(()) // caution: .out(("watched", "likes")) does not work
-        .dedup(())
-        .to_list()
-        .expect("Failed to get videos")
-        .to_owned()
-}
-
-fn get_videos_not_seen_by_user_id(user_id: Uuid) -> Vec<gremlin_client::Vertex> {
-    // Get videos not seen or not liked by user
-    let g = get_transversal();
-
-    // Get all videos, then filter out the videos the user has seen or liked
-    g.v(())
-        .has_label("video")
-        .not(
-            __.in_(())
-                .has(("user", "user_id", user_id.to_string()))
-                .dedup(()),
-        )
-        .to_list()
-        .expect("Failed to get videos")
-        .to_owned()
-}
-
-fn calculate_recommendation_score(
-    watched_videos: &[Vertex],
-    not_watched_videos: &[Vertex],
-) -> Vec<VideoRecommendation> {
-    let g = get_transversal();
-    // Initialize recommendations vector
-    let mut recommendations = Vec::new();
-
-    // For each video not watched by the user, calculate the recommendation score
-    for not_watched_video_vertex in not_watched_videos.iter() {
-        let property = g
-            .v(not_watched_video_vertex.id())
-            .properties("video_id")
-            .next();
-        if let Ok(Some(property)) = property {
-            let not_watched_video_id = property.value().get::<String>().unwrap();
-
-            let mut total_similarity = 0.0;
-            let mut total_weight = 0.0;
-            // For each video watched by the user of each not watched video, get the similarity score
-            // Add the similarity score to the total similarity and increment the total
-            // weight of the recommendation score
-            for watched_video_vertex in watched_videos {
-                let edge = g
-                    .v(watched_video_vertex.id())
-                    .out("similar_to")
-                    .has(("video_id", not_watched_video_id.to_owned()))
-                    .out_e("similar_to")
-                    .next();
-
-                if let Ok(Some(edge)) = edge {
-                    let property = g.e(edge.id()).properties("similarity").next();
-                    if let Ok(Some(property)) = property {
-                        let similarity = property.value().get::<f64>().unwrap();
-                        total_similarity += similarity;
-                        total_weight += 1.0;
-                    } else {
-                        println!("No property found");
-                    }
-                } else {
-                    println!("No edge found");
-                }
-            }
 
-            // Calculate the average similarity score
-            let recommendation_score = total_similarity / total_weight;
-
-            let property = g
-                .v(not_watched_video_vertex.id())
-                .properties("title")
-                .next();
-
-            if let Ok(Some(property)) = property {
-                let video_title = property.value().get::<String>().unwrap();
+use crate::{
+    audience::get_video_audience,
+    config::Config,
+    continue_watching::get_continue_watching,
+    dismissals::{delete_dismissed, get_dismissed, post_dismissed},
+    events::post_event,
+    feed::get_user_feed,
+    onboarding::{get_onboarding_candidates, post_user_interests},
+    playlists::get_playlist,
+    popularity::{get_popular_videos, get_trending_videos},
+    publishers::get_recommended_publishers,
+    recommendations::{get_user_recommends, post_batch_recommends},
+    similar::get_similar_videos,
+    similar_users::get_similar_users,
+    snapshot::{reload_on_request, reload_snapshot, watch},
+    state::AppState,
+};
 
-                recommendations.push(VideoRecommendation {
-                    id: not_watched_video_id.to_owned(),
-                    title: video_title.to_string(),
-                    score: recommendation_score as f32,
-                });
-            }
+#[tokio::main]
+async fn main() {
+    let config = Config::from_env();
+    let state = AppState::new(config.clone()).await;
+    if let Some(store) = &state.snapshot {
+        reload_on_request(
+            store.clone(),
+            Duration::from_secs(config.snapshot_reload_delay_seconds),
+        );
+        if !config.snapshot_watch_file.is_empty() {
+            watch(
+                store.clone(),
+                config.snapshot_watch_file.into(),
+                Duration::from_secs(config.snapshot_poll_seconds),
+            );
         }
     }
-    // Sort the recommendations by score
-    recommendations.sort_by(|a, b| b.score.partial_cmp(&a.score).unwrap());
-    recommendations
-}
-
-async fn get_user_recommends(
-    Path(user_id): Path<Uuid>,
-    Query(query_params): Query<Params>,
-) -> axum::Json<Vec<VideoRecommendation>> {
-    /*
-    get videos seen and liked by user
-    get videos unseen by user
-    sort unseen videos by score
-    return the top 10 videos
-     */
-    let watched_videos = get_videos_watched_by_user_id(user_id);
-    println!("watched_videos {}", watched_videos.len(),);
 
-    let not_watched_videos = get_videos_not_seen_by_user_id(user_id);
-    println!("not_watched_videos {}", not_watched_videos.len(),);
-
-    println!("Calculating recommendation score...");
-    let recommendations = calculate_recommendation_score(&watched_videos, &not_watched_videos);
-
-    // Limit the number of recommendations
-    let limit = query_params.limit.unwrap_or(10);
-    let recommendations = recommendations.into_iter().take(limit as usize).collect();
-
-    // Return an empty list for now
-    println!("Returning recommendations");
-    axum::Json(recommendations)
-}
-
-#[tokio::main]
-async fn main() {
     // // build our application with a single route
-    let app = Router::new().route("/recommendations/:id", get(get_user_recommends));
+    let app = Router::new()
+        .route("/recommendations/batch", post(post_batch_recommends))
+        .route("/recommendations/:id", get(get_user_recommends))
+        .route("/videos/popular", get(get_popular_videos))
+        .route("/videos/trending", get(get_trending_videos))
+        .route("/videos/:id/similar", get(get_similar_videos))
+        .route("/videos/:id/audience", get(get_video_audience))
+        .route("/users/:id/feed", get(get_user_feed))
+        .route("/users/:id/interests", post(post_user_interests))
+        .route("/users/:id/similar", get(get_similar_users))
+        .route(
+            "/users/:id/recommended-publishers",
+            get(get_recommended_publishers),
+        )
+        .route("/users/:id/continue-watching", get(get_continue_watching))
+        .route(
+            "/users/:id/dismissed",
+            get(get_dismissed).post(post_dismissed),
+        )
+        .route("/users/:id/dismissed/:video_id", delete(delete_dismissed))
+        .route("/playlists/generate", get(get_playlist))
+        .route("/onboarding/candidates", get(get_onboarding_candidates))
+        .route("/events", post(post_event))
+        .route("/admin/reload", post(reload_snapshot))
+        .with_state(state);
 
     // run it with hyper on localhost:3000
     println!("Listening on http://localhost:3000");
-    println!("Try: curl http://localhost:3000/recommendations/b8d26a9a-af81-4cec-abf9-1bac3101c8d0");
+    println!(
+        "Try: curl http://localhost:3000/recommendations/b8d26a9a-af81-4cec-abf9-1bac3101c8d0"
+    );
     axum::Server::bind(&"0.0.0.0:3000".parse().unwrap())
         .serve(app.into_make_service())
         .await
@@ -0,0 +1,203 @@
+use std::collections::{BTreeMap, HashMap};
+
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::{
+    error::{AppError, AppResult},
+    scoring::Aggregation,
+};
+
+// Number of videos returned when the request doesn't say, and the most it can ask for
+pub const DEFAULT_LIMIT: i32 = 10;
+pub const MAX_LIMIT: i32 = 100;
+// Most recommendations a request can skip with `offset` or `cursor`
+pub const MAX_OFFSET: usize = 10_000;
+
+pub fn split_list(value: &Option<String>) -> Vec<String> {
+    // Comma separated values of a query parameter
+    value
+        .iter()
+        .flat_map(|value| value.split(','))
+        .map(|item| item.trim().to_string())
+        .filter(|item| !item.is_empty())
+        .collect()
+}
+
+pub fn get_limit(limit: Option<i32>) -> AppResult<usize> {
+    match limit.unwrap_or(DEFAULT_LIMIT) {
+        limit @ 1..=MAX_LIMIT => Ok(limit as usize),
+        limit => Err(AppError::BadRequest(format!(
+            "limit must be between 1 and {}, got {}",
+            MAX_LIMIT, limit
+        ))),
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct Params {
+    pub limit: Option<i32>,
+    pub aggregation: Option<Aggregation>,
+    pub top_n: Option<usize>,
+    // Name of a blend profile from the configuration
+    pub profile: Option<String>,
+    // Strategy weights overriding the profile, e.g. `content:0.5,popularity:0.5`
+    pub weights: Option<String>,
+    // Between 0 (pure relevance) and 1 (pure novelty compared to the videos already picked)
+    pub diversity: Option<f64>,
+    // Maximum number of videos of a same publisher in the page, 0 for no cap
+    pub max_per_publisher: Option<usize>,
+    // Favor publishers that got fewer impressions so far
+    pub fairness: Option<bool>,
+    // Number of recommendations to skip, `cursor` is the value returned in `x-next-cursor`
+    pub offset: Option<usize>,
+    pub cursor: Option<String>,
+    // Comma separated video ids to leave out, e.g. the ones already on screen
+    pub exclude: Option<String>,
+    // Comma separated publisher ids to keep, or to leave out when prefixed with `-`
+    pub publisher: Option<String>,
+    // Recommendations with a lower score are left out
+    pub min_score: Option<f32>,
+    // Comma separated fields of the recommendations to return
+    pub fields: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct TrendingParams {
+    pub limit: Option<i32>,
+    pub half_life_hours: Option<f64>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct FeedParams {
+    // Number of videos of each shelf
+    pub limit: Option<i32>,
+    // Comma separated shelves, in the order they are shown
+    pub shelves: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ContinueWatchingParams {
+    pub limit: Option<i32>,
+    // `recent` (default) or `remaining`, least remaining time first
+    pub order: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AudienceParams {
+    pub limit: Option<i32>,
+    pub aggregation: Option<Aggregation>,
+    pub top_n: Option<usize>,
+    // Users with a lower affinity are left out
+    pub min_score: Option<f64>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PublisherParams {
+    pub limit: Option<i32>,
+    // Publishers making up at least this share of the user history are left out
+    pub max_share: Option<f64>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PlaylistParams {
+    // Video the playlist starts from
+    pub seed: Option<String>,
+    // User whose liked, watched and dismissed videos are left out,
+    // the playlist follows from their last video when there is no seed
+    pub user_id: Option<Uuid>,
+    pub limit: Option<i32>,
+    // Between 0 (each video as close as possible to the previous one) and 1 (as far as possible from the earlier ones)
+    pub diversity: Option<f64>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct OnboardingParams {
+    // Number of candidates, one per cluster of the catalog
+    pub limit: Option<i32>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SimilarParams {
+    pub limit: Option<i32>,
+    // Videos less similar than this are left out
+    pub min_score: Option<f64>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct VideoRecommendation {
+    pub id: String,
+    pub title: String,
+    pub publisher_id: String,
+    pub score: f32,
+    // Part of the score coming from each strategy
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub contributions: Option<BTreeMap<String, f32>>,
+    // Videos of the history and strategies that contributed the most to the score
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reasons: Option<Vec<Reason>>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Reason {
+    pub strategy: String,
+    // Liked or watched video behind the reason, none for the strategies not based on the history
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub video_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    // Part of the score coming from this reason
+    pub contribution: f32,
+}
+
+#[derive(Debug, Clone)]
+pub struct VideoMeta {
+    pub video_id: String,
+    pub title: String,
+    pub publisher_id: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum InteractionKind {
+    Like,
+    Watch,
+}
+
+#[derive(Debug, Clone)]
+pub struct Interaction {
+    pub user_id: String,
+    pub video_id: String,
+    pub kind: InteractionKind,
+    // Only set on `watched` edges
+    pub watch_percentage: Option<f64>,
+    // Unix timestamp in seconds, when the edge carries one
+    pub timestamp: Option<i64>,
+    // Likes recorded from the onboarding picks of the user
+    pub seed: bool,
+}
+
+// A video the user started without finishing it (`in_progress` edge)
+#[derive(Debug, Clone)]
+pub struct PartialView {
+    pub user_id: String,
+    pub video_id: String,
+    // Seconds watched, when the event gave it
+    pub watch_time: Option<f64>,
+    // Between 0 and 1
+    pub watch_percentage: f64,
+    pub timestamp: Option<i64>,
+}
+
+// A video the user said they're not interested in
+#[derive(Debug, Clone, Serialize)]
+pub struct Dismissal {
+    pub user_id: String,
+    pub video_id: String,
+    pub publisher_id: String,
+    // Whether every video of the publisher is hidden too
+    pub block_publisher: bool,
+    pub timestamp: Option<i64>,
+}
+
+// Similarity between two videos, indexed by the id of the first one then the second one
+pub type Similarities = HashMap<String, HashMap<String, f64>>;
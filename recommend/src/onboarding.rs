@@ -0,0 +1,299 @@
+use std::collections::{BTreeMap, HashMap};
+
+use axum::{
+    extract::{
+        rejection::{JsonRejection, PathRejection, QueryRejection},
+        Path, Query, State,
+    },
+    Json,
+};
+use gremlin_client::GValue;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::{
+    error::{AppError, AppResult},
+    events::reload_on_change,
+    graph::EdgeChange,
+    models::{get_limit, OnboardingParams, Similarities, VideoMeta, MAX_LIMIT},
+    popularity::now,
+    rerank::get_similarity,
+    state::AppState,
+};
+
+// Number of times the clusters are refined once their first centers are picked
+const CLUSTERING_ITERATIONS: usize = 5;
+
+#[derive(Debug, Serialize)]
+pub struct OnboardingCandidate {
+    pub id: String,
+    pub title: String,
+    pub publisher_id: String,
+    // Number of videos of the catalog this video stands for
+    pub cluster_size: usize,
+}
+
+fn distance(similarities: &Similarities, video_a: &str, video_b: &str) -> f64 {
+    if video_a == video_b {
+        return 0.0;
+    }
+    1.0 - get_similarity(similarities, video_a, video_b)
+}
+
+fn assign(videos: &[VideoMeta], centers: &[usize], similarities: &Similarities) -> Vec<Vec<usize>> {
+    // Put each video in the cluster of its closest center, the first one on ties
+    let mut clusters = vec![Vec::new(); centers.len()];
+    for (index, video) in videos.iter().enumerate() {
+        let closest = centers
+            .iter()
+            .enumerate()
+            .map(|(cluster, center)| {
+                let center = &videos[*center].video_id;
+                (cluster, distance(similarities, center, &video.video_id))
+            })
+            .fold((0, f64::INFINITY), |best, current| {
+                if current.1 < best.1 {
+                    current
+                } else {
+                    best
+                }
+            })
+            .0;
+        clusters[closest].push(index);
+    }
+    clusters
+}
+
+pub fn cluster_catalog(
+    videos: &[VideoMeta],
+    similarities: &Similarities,
+    popularity: &HashMap<String, f64>,
+    count: usize,
+) -> Vec<OnboardingCandidate> {
+    // K-medoids over the `similar_to` edges, the distance between two videos being 1 - similarity.
+    // The first centers are picked farthest first from the most popular video, then each cluster
+    // gets as center the video closest to all the others, the most popular one on ties.
+    let count = count.min(videos.len());
+    if count == 0 {
+        return Vec::new();
+    }
+    let popularity_of = |index: usize| {
+        popularity
+            .get(&videos[index].video_id)
+            .copied()
+            .unwrap_or(0.0)
+    };
+    let by_popularity = |a: &usize, b: &usize| {
+        popularity_of(*b)
+            .total_cmp(&popularity_of(*a))
+            .then_with(|| videos[*a].video_id.cmp(&videos[*b].video_id))
+    };
+
+    let mut order: Vec<usize> = (0..videos.len()).collect();
+    order.sort_by(by_popularity);
+    let mut centers = vec![order[0]];
+    while centers.len() < count {
+        // The video farthest from every center so far, the most popular one on ties
+        let next = order
+            .iter()
+            .filter(|index| !centers.contains(index))
+            .map(|index| {
+                let nearest = centers
+                    .iter()
+                    .map(|center| {
+                        distance(
+                            similarities,
+                            &videos[*center].video_id,
+                            &videos[*index].video_id,
+                        )
+                    })
+                    .fold(f64::INFINITY, f64::min);
+                (*index, nearest)
+            })
+            .fold((usize::MAX, f64::NEG_INFINITY), |best, current| {
+                if current.1 > best.1 {
+                    current
+                } else {
+                    best
+                }
+            })
+            .0;
+        centers.push(next);
+    }
+
+    let mut clusters = assign(videos, &centers, similarities);
+    for _ in 0..CLUSTERING_ITERATIONS {
+        let new_centers: Vec<usize> = clusters
+            .iter()
+            .zip(centers.iter())
+            .map(|(members, center)| {
+                let mut members = members.clone();
+                members.sort_by(by_popularity);
+                members
+                    .iter()
+                    .map(|candidate| {
+                        let total: f64 = members
+                            .iter()
+                            .map(|member| {
+                                distance(
+                                    similarities,
+                                    &videos[*candidate].video_id,
+                                    &videos[*member].video_id,
+                                )
+                            })
+                            .sum();
+                        (*candidate, total)
+                    })
+                    .fold((*center, f64::INFINITY), |best, current| {
+                        if current.1 < best.1 {
+                            current
+                        } else {
+                            best
+                        }
+                    })
+                    .0
+            })
+            .collect();
+        if new_centers == centers {
+            break;
+        }
+        centers = new_centers;
+        clusters = assign(videos, &centers, similarities);
+    }
+
+    // Largest clusters first, they stand for more of the catalog
+    let mut candidates: Vec<OnboardingCandidate> = centers
+        .iter()
+        .zip(clusters.iter())
+        .map(|(center, members)| OnboardingCandidate {
+            id: videos[*center].video_id.clone(),
+            title: videos[*center].title.clone(),
+            publisher_id: videos[*center].publisher_id.clone(),
+            cluster_size: members.len(),
+        })
+        .collect();
+    candidates.sort_by(|a, b| {
+        b.cluster_size
+            .cmp(&a.cluster_size)
+            .then_with(|| a.id.cmp(&b.id))
+    });
+    candidates
+}
+
+pub async fn get_onboarding_candidates(
+    State(state): State<AppState>,
+    query: Result<Query<OnboardingParams>, QueryRejection>,
+) -> AppResult<Json<Vec<OnboardingCandidate>>> {
+    let Query(query_params) = query?;
+    // `limit` is the number of clusters
+    let limit = get_limit(query_params.limit)?;
+
+    let data = state.data();
+    let candidates = cluster_catalog(
+        data.get_videos().await?,
+        data.get_similarities().await?,
+        data.get_popularity().await?,
+        limit,
+    );
+    Ok(Json(candidates))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct InterestsRequest {
+    // Videos picked by the user, usually among the onboarding candidates
+    pub video_ids: Vec<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct InterestsResult {
+    // What happened to the `likes` edge of each video
+    pub changes: BTreeMap<String, EdgeChange>,
+}
+
+pub async fn post_user_interests(
+    State(state): State<AppState>,
+    path: Result<Path<Uuid>, PathRejection>,
+    body: Result<Json<InterestsRequest>, JsonRejection>,
+) -> AppResult<Json<InterestsResult>> {
+    let Path(user_id) = path?;
+    let Json(request) = body?;
+    if request.video_ids.is_empty() || request.video_ids.len() > MAX_LIMIT as usize {
+        return Err(AppError::BadRequest(format!(
+            "Between 1 and {} videos can be picked, got {}",
+            MAX_LIMIT,
+            request.video_ids.len()
+        )));
+    }
+    // Every video is checked before anything is written, so that a typo doesn't leave half the picks
+    for video_id in request.video_ids.iter() {
+        if !state.graph.video_exists(video_id).await? {
+            return Err(AppError::UnknownVideo(video_id.clone()));
+        }
+    }
+
+    // Brand-new users may not be in the graph yet
+    if !state.graph.user_exists(user_id).await? {
+        println!("Adding user {}", user_id);
+        state.graph.add_user(user_id).await?;
+    }
+
+    // Picks are recorded as likes, marked as `seed` to tell them apart from the real ones,
+    // which are left as they are
+    let timestamp = now();
+    let mut changes = BTreeMap::new();
+    for video_id in request.video_ids {
+        let change = if state
+            .graph
+            .has_user_edge("likes", user_id, &video_id)
+            .await?
+        {
+            EdgeChange::Unchanged
+        } else {
+            let properties = vec![
+                ("timestamp", GValue::from(timestamp)),
+                ("seed", GValue::from(true)),
+            ];
+            state
+                .graph
+                .set_user_edge("likes", user_id, &video_id, properties)
+                .await?
+        };
+        reload_on_change(&state, change);
+        changes.insert(video_id, change);
+    }
+    Ok(Json(InterestsResult { changes }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::context::tests::{ids, similarities, video};
+
+    #[test]
+    fn cluster_catalog_picks_one_video_per_topic() {
+        let videos: Vec<VideoMeta> = ["a", "b", "c", "x", "y"]
+            .iter()
+            .map(|video_id| video(video_id, "publisher"))
+            .collect();
+        let edges = similarities(&[
+            ("a", "b", 0.9),
+            ("a", "c", 0.9),
+            ("b", "c", 0.9),
+            ("x", "y", 0.8),
+        ]);
+        let popularity = HashMap::from([
+            ("b".to_string(), 5.0),
+            ("x".to_string(), 1.0),
+            ("y".to_string(), 2.0),
+        ]);
+
+        // The most popular video of each topic, the largest topic first
+        let candidates = cluster_catalog(&videos, &edges, &popularity, 2);
+        assert_eq!(ids(&candidates, |candidate| &candidate.id), vec!["b", "y"]);
+        assert_eq!(candidates[0].cluster_size, 3);
+        assert_eq!(candidates[1].cluster_size, 2);
+
+        assert_eq!(cluster_catalog(&videos, &edges, &popularity, 10).len(), 5);
+        assert!(cluster_catalog(&videos, &edges, &popularity, 0).is_empty());
+    }
+}
@@ -0,0 +1,296 @@
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Arc,
+};
+
+use crate::{
+    collaborative::CollaborativeScorer,
+    config::Config,
+    context::{Page, RecommendationContext},
+    hybrid::{blend, Strategy},
+    models::{Reason, VideoMeta, VideoRecommendation},
+    popularity::PopularityScorer,
+    rerank::{ExposureTracker, FairnessReranker, MmrReranker, PublisherCapReranker},
+    scoring::ContentScorer,
+};
+
+// Number of reasons given for each recommendation
+const MAX_REASONS: usize = 3;
+
+pub trait CandidateGenerator: Send + Sync {
+    // Videos that may be recommended
+    fn generate(&self, context: &RecommendationContext) -> Vec<VideoMeta>;
+}
+
+pub trait Scorer: Send + Sync {
+    fn strategy(&self) -> Strategy;
+    // Raw score of the candidates this strategy can score, they are normalized when blended
+    fn score(
+        &self,
+        context: &RecommendationContext,
+        candidates: &[VideoMeta],
+    ) -> HashMap<String, f64>;
+    // Videos of the history behind the score of a video, with how much each of them weighs in it.
+    // Strategies not based on the history have none.
+    fn explain(&self, _context: &RecommendationContext, _video_id: &str) -> Vec<(String, f64)> {
+        Vec::new()
+    }
+}
+
+pub trait Filter: Send + Sync {
+    fn keep(&self, context: &RecommendationContext, recommendation: &VideoRecommendation) -> bool;
+}
+
+pub trait Reranker: Send + Sync {
+    fn rerank(
+        &self,
+        context: &RecommendationContext,
+        recommendations: Vec<VideoRecommendation>,
+    ) -> Vec<VideoRecommendation>;
+}
+
+pub struct UnseenGenerator;
+
+impl CandidateGenerator for UnseenGenerator {
+    fn generate(&self, context: &RecommendationContext) -> Vec<VideoMeta> {
+        // Every video of the catalog the user hasn't liked or watched
+        context
+            .videos
+            .iter()
+            .filter(|video| !context.is_seen(&video.video_id))
+            .cloned()
+            .collect()
+    }
+}
+
+pub struct SeenFilter;
+
+impl Filter for SeenFilter {
+    fn keep(&self, context: &RecommendationContext, recommendation: &VideoRecommendation) -> bool {
+        !context.is_seen(&recommendation.id)
+    }
+}
+
+pub struct Pipeline {
+    generators: Vec<Box<dyn CandidateGenerator>>,
+    scorers: Vec<Box<dyn Scorer>>,
+    filters: Vec<Box<dyn Filter>>,
+    rerankers: Vec<Box<dyn Reranker>>,
+}
+
+impl Pipeline {
+    pub fn from_config(config: &Config, exposure: Arc<ExposureTracker>) -> Pipeline {
+        // Stages are listed by name in the configuration, in the order they run
+        let generators = config
+            .pipeline_generators
+            .iter()
+            .map(|name| -> Box<dyn CandidateGenerator> {
+                match name.as_str() {
+                    "unseen" => Box::new(UnseenGenerator),
+                    _ => panic!("Unknown candidate generator: {}", name),
+                }
+            })
+            .collect();
+        let scorers = config
+            .pipeline_scorers
+            .iter()
+            .map(|name| -> Box<dyn Scorer> {
+                match name.parse::<Strategy>() {
+                    Ok(Strategy::Content) => Box::new(ContentScorer),
+                    Ok(Strategy::Collaborative) => Box::new(CollaborativeScorer),
+                    Ok(Strategy::Popularity) => Box::new(PopularityScorer),
+                    Err(err) => panic!("{}", err),
+                }
+            })
+            .collect();
+        let filters = config
+            .pipeline_filters
+            .iter()
+            .map(|name| -> Box<dyn Filter> {
+                match name.as_str() {
+                    "seen" => Box::new(SeenFilter),
+                    _ => panic!("Unknown filter: {}", name),
+                }
+            })
+            .collect();
+        let rerankers = config
+            .pipeline_rerankers
+            .iter()
+            .map(|name| -> Box<dyn Reranker> {
+                match name.as_str() {
+                    "fairness" => Box::new(FairnessReranker {
+                        exposure: exposure.clone(),
+                        strength: config.fairness_strength,
+                    }),
+                    "diversity" => Box::new(MmrReranker),
+                    "publisher_cap" => Box::new(PublisherCapReranker),
+                    _ => panic!("Unknown reranker: {}", name),
+                }
+            })
+            .collect();
+
+        Pipeline {
+            generators,
+            scorers,
+            filters,
+            rerankers,
+        }
+    }
+
+    pub fn rank(&self, context: &RecommendationContext) -> Vec<VideoRecommendation> {
+        // Gather the candidates of every generator, without duplicates
+        let mut seen = HashSet::new();
+        let candidates: Vec<VideoMeta> = self
+            .generators
+            .iter()
+            .flat_map(|generator| generator.generate(context))
+            .filter(|video| seen.insert(video.video_id.clone()))
+            .collect();
+
+        // Only the strategies with a weight are computed
+        let strategy_scores: Vec<(Strategy, HashMap<String, f64>)> = self
+            .scorers
+            .iter()
+            .filter(|scorer| context.options.uses(scorer.strategy()))
+            .map(|scorer| (scorer.strategy(), scorer.score(context, &candidates)))
+            .collect();
+        let mut recommendations = blend(&candidates, &strategy_scores, &context.options.weights);
+
+        // The filters of the request and the blocklist of the user run with the configured ones,
+        // before anything is cut
+        recommendations.retain(|recommendation| {
+            self.filters
+                .iter()
+                .all(|filter| filter.keep(context, recommendation))
+                && context.options.keeps(recommendation)
+                && !context.blocklist.blocks(recommendation)
+        });
+
+        // The whole ranking is re-ranked, whatever the page, so that pages don't overlap
+        for reranker in self.rerankers.iter() {
+            recommendations = reranker.rerank(context, recommendations);
+        }
+        recommendations
+    }
+
+    pub fn recommend(&self, context: &RecommendationContext) -> Page {
+        // Only the returned page is explained
+        let mut page = context.options.page(self.rank(context));
+        for recommendation in page.videos.iter_mut() {
+            recommendation.reasons = Some(self.explain(context, recommendation));
+        }
+        page
+    }
+
+    fn explain(
+        &self,
+        context: &RecommendationContext,
+        recommendation: &VideoRecommendation,
+    ) -> Vec<Reason> {
+        // Split the contribution of each strategy between the videos of the history behind it,
+        // in proportion to their weight and similarity
+        let Some(contributions) = &recommendation.contributions else {
+            return Vec::new();
+        };
+        let mut reasons = Vec::new();
+        for scorer in self.scorers.iter() {
+            let strategy = scorer.strategy().name();
+            let Some(contribution) = contributions.get(strategy).copied() else {
+                continue;
+            };
+            if contribution <= 0.0 {
+                continue;
+            }
+            let shares: Vec<(String, f64)> = scorer
+                .explain(context, &recommendation.id)
+                .into_iter()
+                .filter(|(_, share)| *share > 0.0)
+                .collect();
+            let total_share: f64 = shares.iter().map(|(_, share)| share).sum();
+            if shares.is_empty() {
+                reasons.push(Reason {
+                    strategy: strategy.to_string(),
+                    video_id: None,
+                    title: None,
+                    contribution,
+                });
+                continue;
+            }
+            for (video_id, share) in shares {
+                reasons.push(Reason {
+                    strategy: strategy.to_string(),
+                    title: context.title(&video_id),
+                    video_id: Some(video_id),
+                    contribution: (contribution as f64 * share / total_share) as f32,
+                });
+            }
+        }
+        reasons.sort_by(|a, b| {
+            b.contribution
+                .total_cmp(&a.contribution)
+                .then_with(|| a.video_id.cmp(&b.video_id))
+        });
+        reasons.truncate(MAX_REASONS);
+        reasons
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        config::tests::config,
+        context::tests::{context, ids, options, similarities, video},
+        source::content_similarities,
+    };
+
+    #[test]
+    fn recommend_pages_the_unseen_videos() {
+        let videos = vec![
+            video("seen", "a"),
+            video("a1", "a"),
+            video("a2", "a"),
+            video("a3", "a"),
+            video("b1", "b"),
+            video("blocked", "c"),
+            video("unrelated", "b"),
+        ];
+        let edges = similarities(&[
+            ("seen", "a1", 0.9),
+            ("seen", "a2", 0.8),
+            ("seen", "a3", 0.7),
+            ("seen", "b1", 0.6),
+            ("seen", "blocked", 1.0),
+        ]);
+        let mut options = options();
+        options.limit = 2;
+        options.max_per_publisher = 1;
+        let mut context = context(&videos, &[("seen", 1.0)], options);
+        context.blocklist.publishers.insert("c".to_string());
+        let not_seen: Vec<String> = ids(&videos[1..], |video| &video.video_id);
+        context.content_similarities =
+            content_similarities(&edges, &["seen".to_string()], &not_seen);
+        let pipeline = Pipeline::from_config(&config(), Arc::new(ExposureTracker::default()));
+
+        // One video of each publisher in the first page, the videos without a score are left out
+        let page = pipeline.recommend(&context);
+        assert_eq!(
+            ids(&page.videos, |recommendation| &recommendation.id),
+            vec!["a1", "b1"]
+        );
+        assert_eq!(page.next_offset, Some(2));
+        let reasons = page.videos[0].reasons.as_ref().unwrap();
+        assert_eq!(reasons.len(), 1);
+        assert_eq!(reasons[0].video_id.as_deref(), Some("seen"));
+        assert_eq!(reasons[0].title.as_deref(), Some("Title of seen"));
+
+        // The last page has no next one
+        context.options.offset = 2;
+        let page = pipeline.recommend(&context);
+        assert_eq!(
+            ids(&page.videos, |recommendation| &recommendation.id),
+            vec!["a2", "a3"]
+        );
+        assert_eq!(page.next_offset, None);
+    }
+}
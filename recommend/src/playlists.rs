@@ -0,0 +1,241 @@
+use std::collections::HashSet;
+
+use axum::{
+    extract::{rejection::QueryRejection, Query, State},
+    Json,
+};
+use serde::Serialize;
+
+use crate::{
+    context::Blocklist,
+    error::{AppError, AppResult},
+    models::{
+        get_limit, Interaction, PlaylistParams, Similarities, VideoMeta, VideoRecommendation,
+    },
+    popularity::get_popular_ranking,
+    rerank::get_similarity,
+    state::AppState,
+};
+
+// Weight of the similarity to the earlier videos of the playlist, so that it doesn't go round in circles
+const PLAYLIST_DIVERSITY: f64 = 0.3;
+
+#[derive(Debug, Serialize)]
+pub struct Playlist {
+    pub seed: String,
+    // In order, the score of a video is its similarity to the one it follows from
+    pub videos: Vec<VideoRecommendation>,
+}
+
+pub fn generate_playlist(
+    seed: &str,
+    include_seed: bool,
+    videos: &[VideoMeta],
+    similarities: &Similarities,
+    excluded: &HashSet<String>,
+    diversity: f64,
+    limit: usize,
+) -> Vec<VideoRecommendation> {
+    // Walk the `similar_to` edges from the seed: each step takes the unplayed video most similar
+    // to the previous one, minus its similarity to the earlier ones. At a dead end, the walk goes
+    // on from the latest video that still has an unplayed neighbor.
+    let mut remaining: Vec<&VideoMeta> = videos
+        .iter()
+        .filter(|video| video.video_id != seed && !excluded.contains(&video.video_id))
+        .collect();
+    let mut path: Vec<String> = vec![seed.to_string()];
+    let mut playlist: Vec<VideoRecommendation> = Vec::new();
+    if include_seed {
+        if let Some(video) = videos.iter().find(|video| video.video_id == seed) {
+            playlist.push(VideoRecommendation {
+                id: video.video_id.clone(),
+                title: video.title.clone(),
+                publisher_id: video.publisher_id.clone(),
+                score: 1.0,
+                contributions: None,
+                reasons: None,
+            });
+        }
+    }
+
+    while playlist.len() < limit && !remaining.is_empty() {
+        let next = path.iter().rev().find_map(|previous| {
+            remaining
+                .iter()
+                .enumerate()
+                .filter_map(|(index, candidate)| {
+                    let flow = get_similarity(similarities, previous, &candidate.video_id);
+                    if flow <= 0.0 {
+                        return None;
+                    }
+                    let redundancy = path
+                        .iter()
+                        .filter(|played| *played != previous)
+                        .map(|played| get_similarity(similarities, played, &candidate.video_id))
+                        .fold(0.0, f64::max);
+                    Some((
+                        index,
+                        flow,
+                        (1.0 - diversity) * flow - diversity * redundancy,
+                    ))
+                })
+                .fold(
+                    None,
+                    |best: Option<(usize, f64, f64)>, current| match best {
+                        // Keep the first one on ties so that the catalog order breaks them
+                        Some(best) if best.2 >= current.2 => Some(best),
+                        _ => Some(current),
+                    },
+                )
+        });
+        let Some((index, flow, _)) = next else {
+            break;
+        };
+        let video = remaining.remove(index);
+        path.push(video.video_id.clone());
+        playlist.push(VideoRecommendation {
+            id: video.video_id.clone(),
+            title: video.title.clone(),
+            publisher_id: video.publisher_id.clone(),
+            score: flow as f32,
+            contributions: None,
+            reasons: None,
+        });
+    }
+    playlist
+}
+
+fn last_video(history: &[Interaction], blocklist: &Blocklist) -> Option<String> {
+    // Most recent liked or watched video, the ones without a timestamp count as the oldest
+    history
+        .iter()
+        .filter(|interaction| !blocklist.videos.contains(&interaction.video_id))
+        .max_by_key(|interaction| interaction.timestamp)
+        .map(|interaction| interaction.video_id.clone())
+}
+
+pub async fn get_playlist(
+    State(state): State<AppState>,
+    query: Result<Query<PlaylistParams>, QueryRejection>,
+) -> AppResult<Json<Playlist>> {
+    let Query(query_params) = query?;
+    let limit = get_limit(query_params.limit)?;
+    let diversity = query_params
+        .diversity
+        .unwrap_or(PLAYLIST_DIVERSITY)
+        .clamp(0.0, 1.0);
+    if query_params.seed.is_none() && query_params.user_id.is_none() {
+        return Err(AppError::BadRequest(
+            "seed or user_id is required".to_string(),
+        ));
+    }
+
+    let data = state.data();
+    let videos = data.get_videos().await?;
+    let similarities = data.get_similarities().await?;
+    if let Some(seed) = &query_params.seed {
+        data.get_video(seed).await?;
+    }
+
+    // With a user, the videos they liked, watched or dismissed are left out
+    let (history, blocklist) = match query_params.user_id {
+        Some(user_id) => {
+            data.check_user(user_id).await?;
+            (
+                data.get_user_history(user_id).await?,
+                Blocklist::new(&data.get_user_dismissals(user_id).await?),
+            )
+        }
+        None => (Vec::new(), Blocklist::default()),
+    };
+    let excluded: HashSet<String> = history
+        .iter()
+        .map(|interaction| interaction.video_id.clone())
+        .chain(blocklist.videos.iter().cloned())
+        .chain(
+            videos
+                .iter()
+                .filter(|video| blocklist.publishers.contains(&video.publisher_id))
+                .map(|video| video.video_id.clone()),
+        )
+        .collect();
+
+    // A given seed starts the playlist, otherwise it follows from the last video of the user,
+    // or from the most popular video they haven't seen
+    let (seed, include_seed) = match &query_params.seed {
+        Some(seed) => (seed.clone(), true),
+        None => match last_video(&history, &blocklist) {
+            Some(video_id) => (video_id, false),
+            None => {
+                let popular = get_popular_ranking(&data)
+                    .await?
+                    .into_iter()
+                    .find(|video| !excluded.contains(&video.id))
+                    .ok_or_else(|| {
+                        AppError::BadRequest("No video left to start a playlist".to_string())
+                    })?;
+                (popular.id, true)
+            }
+        },
+    };
+
+    let videos = generate_playlist(
+        &seed,
+        include_seed,
+        videos,
+        similarities,
+        &excluded,
+        diversity,
+        limit,
+    );
+    Ok(Json(Playlist { seed, videos }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::context::tests::{ids, similarities, video};
+
+    fn playlist(recommendations: &[VideoRecommendation]) -> Vec<String> {
+        ids(recommendations, |recommendation| &recommendation.id)
+    }
+
+    #[test]
+    fn playlist_follows_the_edges_and_backtracks() {
+        let videos: Vec<VideoMeta> = ["a", "b", "c", "d", "e"]
+            .iter()
+            .map(|video_id| video(video_id, "publisher"))
+            .collect();
+        let edges = similarities(&[("a", "b", 0.9), ("b", "c", 0.8), ("a", "d", 0.5)]);
+
+        let videos_played = generate_playlist("a", true, &videos, &edges, &HashSet::new(), 0.0, 10);
+        // `e` can't be reached
+        assert_eq!(playlist(&videos_played), vec!["a", "b", "c", "d"]);
+        assert_eq!(videos_played[3].score, 0.5);
+
+        let excluded = HashSet::from(["c".to_string()]);
+        let videos_played = generate_playlist("a", false, &videos, &edges, &excluded, 0.0, 10);
+        assert_eq!(playlist(&videos_played), vec!["b", "d"]);
+    }
+
+    #[test]
+    fn playlist_avoids_going_back_to_the_earlier_videos() {
+        let videos: Vec<VideoMeta> = ["a", "b", "c", "d"]
+            .iter()
+            .map(|video_id| video(video_id, "publisher"))
+            .collect();
+        let edges = similarities(&[
+            ("a", "b", 0.9),
+            ("b", "c", 0.85),
+            ("a", "c", 0.8),
+            ("b", "d", 0.8),
+        ]);
+
+        let without_diversity =
+            generate_playlist("a", false, &videos, &edges, &HashSet::new(), 0.0, 2);
+        assert_eq!(playlist(&without_diversity), vec!["b", "c"]);
+        let with_diversity =
+            generate_playlist("a", false, &videos, &edges, &HashSet::new(), 0.3, 2);
+        assert_eq!(playlist(&with_diversity), vec!["b", "d"]);
+    }
+}
@@ -0,0 +1,184 @@
+use std::{
+    collections::{HashMap, HashSet},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use axum::extract::{rejection::QueryRejection, Query, State};
+
+use crate::{
+    context::RecommendationContext,
+    error::{AppError, AppResult},
+    hybrid::Strategy,
+    models::{
+        get_limit, Interaction, InteractionKind, Params, TrendingParams, VideoMeta,
+        VideoRecommendation,
+    },
+    pipeline::Scorer,
+    source::DataSource,
+    state::AppState,
+};
+
+// Half-life of an interaction in the trending ranking
+pub const TRENDING_HALF_LIFE_HOURS: f64 = 72.0;
+
+pub fn now() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("System clock is before 1970")
+        .as_secs() as i64
+}
+
+pub fn popularity_scores(interactions: &[Interaction]) -> HashMap<String, f64> {
+    // One point per user who liked the video and one per user who completed it
+    // (only watches of 70% or more are stored as `watched` edges), so repeat views are not counted twice
+    let mut seen = HashSet::new();
+    let mut scores = HashMap::new();
+    for interaction in interactions {
+        if seen.insert((
+            &interaction.user_id,
+            &interaction.video_id,
+            interaction.kind,
+        )) {
+            *scores.entry(interaction.video_id.clone()).or_insert(0.0) += 1.0;
+        }
+    }
+    scores
+}
+
+pub fn trending_scores(
+    interactions: &[Interaction],
+    now: i64,
+    half_life_hours: f64,
+) -> HashMap<String, f64> {
+    // Same counts as the popularity, but each interaction loses half its weight every `half_life_hours`.
+    // A repeat view counts once, as of the latest one. Interactions without a timestamp are counted
+    // as if they just happened.
+    let mut latest: HashMap<(&str, &str, InteractionKind), Option<i64>> = HashMap::new();
+    for interaction in interactions {
+        let timestamp = latest
+            .entry((
+                &interaction.user_id,
+                &interaction.video_id,
+                interaction.kind,
+            ))
+            .or_insert(interaction.timestamp);
+        *timestamp = match (*timestamp, interaction.timestamp) {
+            (Some(current), Some(other)) => Some(current.max(other)),
+            _ => None,
+        };
+    }
+
+    let mut scores = HashMap::new();
+    for ((_, video_id, _), timestamp) in latest {
+        let age_hours = timestamp
+            .map(|timestamp| (now - timestamp).max(0) as f64 / 3600.0)
+            .unwrap_or(0.0);
+        let weight = 0.5_f64.powf(age_hours / half_life_hours);
+        *scores.entry(video_id.to_string()).or_insert(0.0) += weight;
+    }
+    scores
+}
+
+pub fn rank_videos(
+    videos: &[VideoMeta],
+    scores: &HashMap<String, f64>,
+) -> Vec<VideoRecommendation> {
+    // Every video is ranked, the ones without any interaction end up last with a score of 0
+    let mut ranking: Vec<VideoRecommendation> = videos
+        .iter()
+        .map(|video| VideoRecommendation {
+            id: video.video_id.clone(),
+            title: video.title.clone(),
+            publisher_id: video.publisher_id.clone(),
+            score: scores.get(&video.video_id).copied().unwrap_or(0.0) as f32,
+            contributions: None,
+            reasons: None,
+        })
+        .collect();
+    ranking.sort_by(|a, b| b.score.total_cmp(&a.score).then_with(|| a.id.cmp(&b.id)));
+    ranking
+}
+
+pub async fn get_popular_ranking(data: &DataSource) -> AppResult<Vec<VideoRecommendation>> {
+    Ok(rank_videos(
+        data.get_videos().await?,
+        data.get_popularity().await?,
+    ))
+}
+
+pub async fn get_popular_videos(
+    State(state): State<AppState>,
+    query: Result<Query<Params>, QueryRejection>,
+) -> AppResult<axum::Json<Vec<VideoRecommendation>>> {
+    let Query(query_params) = query?;
+    let limit = get_limit(query_params.limit)?;
+    let ranking = get_popular_ranking(&state.data()).await?;
+    Ok(axum::Json(ranking.into_iter().take(limit).collect()))
+}
+
+fn get_half_life_hours(half_life_hours: Option<f64>) -> AppResult<f64> {
+    // NaN and infinite half-lives would make every score NaN
+    let half_life_hours = half_life_hours.unwrap_or(TRENDING_HALF_LIFE_HOURS);
+    if !(half_life_hours.is_finite() && half_life_hours > 0.0) {
+        return Err(AppError::BadRequest(format!(
+            "half_life_hours must be a positive number, got {}",
+            half_life_hours
+        )));
+    }
+    Ok(half_life_hours)
+}
+
+pub async fn get_trending_videos(
+    State(state): State<AppState>,
+    query: Result<Query<TrendingParams>, QueryRejection>,
+) -> AppResult<axum::Json<Vec<VideoRecommendation>>> {
+    let Query(query_params) = query?;
+    let limit = get_limit(query_params.limit)?;
+    let half_life_hours = get_half_life_hours(query_params.half_life_hours)?;
+
+    let data = state.data();
+    let ranking = rank_videos(
+        data.get_videos().await?,
+        &trending_scores(data.get_interactions().await?, now(), half_life_hours),
+    );
+    Ok(axum::Json(ranking.into_iter().take(limit).collect()))
+}
+
+pub struct PopularityScorer;
+
+impl Scorer for PopularityScorer {
+    fn strategy(&self) -> Strategy {
+        Strategy::Popularity
+    }
+
+    fn score(
+        &self,
+        context: &RecommendationContext,
+        candidates: &[VideoMeta],
+    ) -> HashMap<String, f64> {
+        candidates
+            .iter()
+            .filter_map(|video| {
+                let score = context.popularity.get(&video.video_id)?;
+                Some((video.video_id.clone(), *score))
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn half_life_must_be_a_positive_number() {
+        assert_eq!(get_half_life_hours(None).unwrap(), TRENDING_HALF_LIFE_HOURS);
+        assert_eq!(get_half_life_hours(Some(12.0)).unwrap(), 12.0);
+        for half_life_hours in [0.0, -1.0, f64::NAN, f64::INFINITY, f64::NEG_INFINITY] {
+            assert!(matches!(
+                get_half_life_hours(Some(half_life_hours)),
+                Err(AppError::BadRequest(_))
+            ));
+        }
+    }
+}
@@ -0,0 +1,158 @@
+use std::{borrow::Cow, collections::HashMap};
+
+use axum::{
+    extract::{
+        rejection::{PathRejection, QueryRejection},
+        Path, Query, State,
+    },
+    Json,
+};
+use serde::Serialize;
+use uuid::Uuid;
+
+use crate::{
+    context::{load_context, Blocklist, RecommendationOptions},
+    error::AppResult,
+    models::{get_limit, Params, PublisherParams, VideoMeta, VideoRecommendation},
+    popularity::{get_popular_ranking, now},
+    scoring::history_weights,
+    state::AppState,
+};
+
+// A publisher is scored by the sum of the scores of its best videos divided by this number,
+// so that large catalogs don't win by size and a single good video isn't enough
+const PUBLISHER_TOP_VIDEOS: usize = 3;
+
+#[derive(Debug, Serialize)]
+pub struct RecommendedPublisher {
+    pub publisher_id: String,
+    pub score: f32,
+    // Number of videos of the publisher
+    pub catalog_size: usize,
+    // Part of the user history coming from this publisher
+    pub history_share: f64,
+    // Best videos of the publisher for the user
+    pub top_videos: Vec<String>,
+}
+
+pub fn get_catalogs<'a>(
+    publishers: &'a HashMap<String, Vec<String>>,
+    videos: &[VideoMeta],
+) -> Cow<'a, HashMap<String, Vec<String>>> {
+    // Graphs stored before publishers had their own vertices only have the publisher_id of the videos
+    if !publishers.is_empty() {
+        return Cow::Borrowed(publishers);
+    }
+    let mut catalogs: HashMap<String, Vec<String>> = HashMap::new();
+    for video in videos {
+        catalogs
+            .entry(video.publisher_id.clone())
+            .or_default()
+            .push(video.video_id.clone());
+    }
+    Cow::Owned(catalogs)
+}
+
+pub fn rank_publishers(
+    recommendations: &[VideoRecommendation],
+    catalogs: &HashMap<String, Vec<String>>,
+    history_shares: &HashMap<String, f64>,
+    max_share: f64,
+) -> Vec<RecommendedPublisher> {
+    // Group the recommendations by publisher, leaving out the publishers the user already watches a lot
+    let mut top_videos: HashMap<&str, Vec<&VideoRecommendation>> = HashMap::new();
+    for recommendation in recommendations {
+        let videos = top_videos
+            .entry(recommendation.publisher_id.as_str())
+            .or_default();
+        // Recommendations are sorted, the first ones are the best
+        if videos.len() < PUBLISHER_TOP_VIDEOS {
+            videos.push(recommendation);
+        }
+    }
+
+    let mut publishers: Vec<RecommendedPublisher> = top_videos
+        .into_iter()
+        .filter_map(|(publisher_id, videos)| {
+            let history_share = history_shares.get(publisher_id).copied().unwrap_or(0.0);
+            if history_share >= max_share {
+                return None;
+            }
+            Some(RecommendedPublisher {
+                publisher_id: publisher_id.to_string(),
+                score: videos.iter().map(|video| video.score).sum::<f32>()
+                    / PUBLISHER_TOP_VIDEOS as f32,
+                catalog_size: catalogs
+                    .get(publisher_id)
+                    .map_or(0, |catalog| catalog.len()),
+                history_share,
+                top_videos: videos.iter().map(|video| video.id.clone()).collect(),
+            })
+        })
+        .collect();
+    publishers.sort_by(|a, b| {
+        b.score
+            .total_cmp(&a.score)
+            .then_with(|| a.publisher_id.cmp(&b.publisher_id))
+    });
+    publishers
+}
+
+pub async fn get_recommended_publishers(
+    State(state): State<AppState>,
+    path: Result<Path<Uuid>, PathRejection>,
+    query: Result<Query<PublisherParams>, QueryRejection>,
+) -> AppResult<Json<Vec<RecommendedPublisher>>> {
+    let Path(user_id) = path?;
+    let Query(query_params) = query?;
+    let limit = get_limit(query_params.limit)?;
+    let max_share = query_params
+        .max_share
+        .unwrap_or(state.config.heavy_publisher_share);
+
+    let data = state.data();
+    data.check_user(user_id).await?;
+    let history = data.get_user_history(user_id).await?;
+    let dismissals = data.get_user_dismissals(user_id).await?;
+    let videos = data.get_videos().await?;
+    let publishers = data.get_publishers().await?;
+    let catalogs = get_catalogs(publishers, videos);
+    let history_weights = history_weights(&history, &state.config, now());
+    let blocklist = Blocklist::new(&dismissals);
+
+    // Share of the weight of the history coming from each publisher
+    let publisher_ids: HashMap<&str, &str> = videos
+        .iter()
+        .map(|video| (video.video_id.as_str(), video.publisher_id.as_str()))
+        .collect();
+    let total_weight: f64 = history_weights.values().sum();
+    let mut history_shares: HashMap<String, f64> = HashMap::new();
+    for (video_id, weight) in history_weights.iter() {
+        if let Some(publisher_id) = publisher_ids.get(video_id.as_str()) {
+            *history_shares
+                .entry(publisher_id.to_string())
+                .or_insert(0.0) += weight / total_weight;
+        }
+    }
+
+    // Every unseen video is scored like for the recommendations, without the page size,
+    // the publisher cap nor the re-rankings that would favor some publishers
+    let recommendations = if history_weights.is_empty() {
+        // Users we know nothing about get the publishers of the most popular videos
+        get_popular_ranking(&data)
+            .await?
+            .into_iter()
+            .filter(|recommendation| !blocklist.blocks(recommendation))
+            .collect()
+    } else {
+        let mut options = RecommendationOptions::new(&Params::default(), &state.config)?;
+        options.max_per_publisher = 0;
+        options.diversity = 0.0;
+        options.fairness = false;
+        let context = load_context(&data, history_weights, blocklist, options).await?;
+        state.pipeline.rank(&context)
+    };
+
+    let publishers = rank_publishers(&recommendations, &catalogs, &history_shares, max_share);
+    Ok(Json(publishers.into_iter().take(limit).collect()))
+}
@@ -0,0 +1,224 @@
+use std::{collections::BTreeMap, sync::Arc};
+
+use axum::{
+    extract::{
+        rejection::{JsonRejection, PathRejection, QueryRejection},
+        Path, Query, State,
+    },
+    http::{HeaderMap, HeaderValue},
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde::{Deserialize, Serialize};
+use tokio::sync::Semaphore;
+use uuid::Uuid;
+
+use crate::{
+    context::{load_context, Blocklist, RecommendationOptions},
+    error::{AppError, AppResult, ErrorBody},
+    models::{split_list, Params, VideoRecommendation},
+    popularity::{get_popular_ranking, now},
+    scoring::history_weights,
+    source::DataSource,
+    state::AppState,
+};
+
+// Header set when the recommendations don't come from the user history
+const FALLBACK_HEADER: &str = "x-recommendation-fallback";
+// Header giving the `cursor` of the next page
+const NEXT_CURSOR_HEADER: &str = "x-next-cursor";
+
+pub struct UserRecommendations {
+    pub videos: Vec<VideoRecommendation>,
+    // Offset of the next page, when there are recommendations left
+    pub next_offset: Option<usize>,
+    // Whether the popular videos were returned because the user has no history
+    pub fallback: bool,
+}
+
+pub async fn recommend_user(
+    state: &AppState,
+    data: &DataSource,
+    user_id: Uuid,
+    options: RecommendationOptions,
+) -> AppResult<UserRecommendations> {
+    /*
+    get videos seen and liked by user
+    get videos unseen by user
+    sort unseen videos by score
+    return the top 10 videos
+     */
+    data.check_user(user_id).await?;
+    let history = data.get_user_history(user_id).await?;
+    let history_weights = history_weights(&history, &state.config, now());
+    println!("watched_videos {}", history_weights.len(),);
+
+    // Dismissed videos and blocked publishers are never recommended, whatever the strategy
+    let blocklist = Blocklist::new(&data.get_user_dismissals(user_id).await?);
+
+    if history_weights.is_empty() {
+        // Users we know nothing about get the most popular videos
+        println!(
+            "User {} has no history, falling back to popular videos",
+            user_id
+        );
+        let mut ranking = get_popular_ranking(data).await?;
+        ranking.retain(|recommendation| {
+            options.keeps(recommendation) && !blocklist.blocks(recommendation)
+        });
+        let page = options.page(ranking);
+        return Ok(UserRecommendations {
+            videos: page.videos,
+            next_offset: page.next_offset,
+            fallback: true,
+        });
+    }
+
+    // Load what the pipeline needs, then generate, score, filter and re-rank the candidates
+    let context = load_context(data, history_weights, blocklist, options).await?;
+    let page = state.pipeline.recommend(&context);
+    state.exposure.record(&page.videos);
+
+    Ok(UserRecommendations {
+        videos: page.videos,
+        next_offset: page.next_offset,
+        fallback: false,
+    })
+}
+
+// Fields of a recommendation that `fields=` can ask for
+const FIELDS: [&str; 6] = [
+    "id",
+    "title",
+    "publisher_id",
+    "score",
+    "contributions",
+    "reasons",
+];
+
+fn project_fields(
+    videos: Vec<VideoRecommendation>,
+    fields: &[String],
+) -> AppResult<Vec<serde_json::Value>> {
+    // Keep only the requested fields of each recommendation
+    if let Some(field) = fields
+        .iter()
+        .find(|field| !FIELDS.contains(&field.as_str()))
+    {
+        return Err(AppError::BadRequest(format!(
+            "Unknown field {}, expected one of {}",
+            field,
+            FIELDS.join(", ")
+        )));
+    }
+    Ok(videos
+        .iter()
+        .map(|video| {
+            let mut value = serde_json::to_value(video).unwrap_or_default();
+            if let serde_json::Value::Object(map) = &mut value {
+                map.retain(|key, _| fields.contains(key));
+            }
+            value
+        })
+        .collect())
+}
+
+pub async fn get_user_recommends(
+    State(state): State<AppState>,
+    path: Result<Path<Uuid>, PathRejection>,
+    query: Result<Query<Params>, QueryRejection>,
+) -> AppResult<Response> {
+    let Path(user_id) = path?;
+    let Query(query_params) = query?;
+    let options = RecommendationOptions::new(&query_params, &state.config)?;
+    let fields = split_list(&query_params.fields);
+
+    let recommendations = recommend_user(&state, &state.data(), user_id, options).await?;
+    println!("Returning recommendations");
+
+    // The cursor is only given when there are recommendations left
+    let mut headers = HeaderMap::new();
+    if let Some(next_offset) = recommendations.next_offset {
+        headers.insert(NEXT_CURSOR_HEADER, HeaderValue::from(next_offset));
+    }
+    if recommendations.fallback {
+        headers.insert(FALLBACK_HEADER, HeaderValue::from_static("popularity"));
+    }
+    if fields.is_empty() {
+        return Ok((headers, Json(recommendations.videos)).into_response());
+    }
+    let videos = project_fields(recommendations.videos, &fields)?;
+    Ok((headers, Json(videos)).into_response())
+}
+
+#[derive(Debug, Deserialize)]
+pub struct BatchRequest {
+    pub user_ids: Vec<Uuid>,
+    // Same parameters as the query of `/recommendations/:id`, shared by every user
+    #[serde(default)]
+    pub params: Params,
+}
+
+#[derive(Serialize, Default)]
+pub struct BatchResponse {
+    pub recommendations: BTreeMap<Uuid, Vec<VideoRecommendation>>,
+    // Users who got the popular videos because they have no history
+    pub fallback: Vec<Uuid>,
+    pub errors: BTreeMap<Uuid, ErrorBody>,
+}
+
+pub async fn post_batch_recommends(
+    State(state): State<AppState>,
+    body: Result<Json<BatchRequest>, JsonRejection>,
+) -> AppResult<Json<BatchResponse>> {
+    let Json(request) = body?;
+    if request.user_ids.len() > state.config.batch_max_users {
+        return Err(AppError::BadRequest(format!(
+            "At most {} users can be requested at once, got {}",
+            state.config.batch_max_users,
+            request.user_ids.len()
+        )));
+    }
+    let options = RecommendationOptions::new(&request.params, &state.config)?;
+
+    // Users are computed concurrently, at most `batch_concurrency` at a time. They share one data
+    // source, so that the catalog is loaded and its derived data computed once for the whole batch.
+    let data = Arc::new(state.data());
+    let semaphore = Arc::new(Semaphore::new(state.config.batch_concurrency.max(1)));
+    let mut tasks = Vec::new();
+    for user_id in request.user_ids {
+        let state = state.clone();
+        let data = data.clone();
+        let options = options.clone();
+        let semaphore = semaphore.clone();
+        let task = tokio::spawn(async move {
+            let _permit = semaphore.acquire_owned().await;
+            recommend_user(&state, &data, user_id, options).await
+        });
+        tasks.push((user_id, task));
+    }
+
+    // A task that panicked still gets its user an error
+    let mut response = BatchResponse::default();
+    for (user_id, task) in tasks {
+        let result = task
+            .await
+            .unwrap_or_else(|err| Err(AppError::Internal(err.to_string())));
+        match result {
+            Ok(recommendations) => {
+                if recommendations.fallback {
+                    response.fallback.push(user_id);
+                }
+                response
+                    .recommendations
+                    .insert(user_id, recommendations.videos);
+            }
+            Err(err) => {
+                println!("Batch recommendations failed for {}: {}", user_id, err);
+                response.errors.insert(user_id, err.body());
+            }
+        }
+    }
+    response.fallback.sort();
+    Ok(Json(response))
+}
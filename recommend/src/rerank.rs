@@ -0,0 +1,326 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+use crate::{
+    context::RecommendationContext,
+    models::{Similarities, VideoRecommendation},
+    pipeline::Reranker,
+};
+
+// Number of best candidates the diversity re-ranking picks from. It is the same for every page,
+// so that a page follows from the previous one; the candidates after it keep their order.
+pub const RERANK_POOL_SIZE: usize = 500;
+
+pub fn get_similarity(similarities: &Similarities, video_a: &str, video_b: &str) -> f64 {
+    // `similar_to` edges go both ways, use whichever is available
+    similarities
+        .get(video_a)
+        .and_then(|neighbors| neighbors.get(video_b))
+        .or_else(|| similarities.get(video_b)?.get(video_a))
+        .copied()
+        .unwrap_or(0.0)
+}
+
+pub fn mmr(
+    candidates: Vec<VideoRecommendation>,
+    similarities: &Similarities,
+    diversity: f64,
+    limit: usize,
+) -> Vec<VideoRecommendation> {
+    // Maximal Marginal Relevance: pick the videos one by one, each time taking the one with the best
+    // trade-off between its relevance and its similarity to the videos already picked
+    let max_score = candidates
+        .iter()
+        .map(|candidate| candidate.score)
+        .fold(0.0, f32::max) as f64;
+    let relevance = |candidate: &VideoRecommendation| {
+        if max_score > 0.0 {
+            candidate.score as f64 / max_score
+        } else {
+            0.0
+        }
+    };
+
+    // Similarity of each remaining candidate to the closest video picked so far
+    let mut remaining = candidates;
+    let mut redundancies = vec![0.0; remaining.len()];
+    let mut selected: Vec<VideoRecommendation> = Vec::new();
+    while selected.len() < limit && !remaining.is_empty() {
+        let (best, _) = remaining
+            .iter()
+            .zip(redundancies.iter())
+            .enumerate()
+            .map(|(index, (candidate, redundancy))| {
+                let mmr_score = (1.0 - diversity) * relevance(candidate) - diversity * redundancy;
+                (index, mmr_score)
+            })
+            .fold((0, f64::NEG_INFINITY), |best, current| {
+                // Keep the first one on ties so that the relevance order is kept
+                if current.1 > best.1 {
+                    current
+                } else {
+                    best
+                }
+            });
+        let picked = remaining.remove(best);
+        redundancies.remove(best);
+        for (candidate, redundancy) in remaining.iter().zip(redundancies.iter_mut()) {
+            *redundancy = get_similarity(similarities, &candidate.id, &picked.id).max(*redundancy);
+        }
+        selected.push(picked);
+    }
+    selected
+}
+
+pub fn cap_publishers(
+    candidates: Vec<VideoRecommendation>,
+    max_per_publisher: usize,
+    page_size: usize,
+    offset: usize,
+) -> Vec<VideoRecommendation> {
+    // Cut the candidates into pages of `page_size`, one of them starting at `offset`, and keep
+    // the order, but move the videos of a publisher that already has `max_per_publisher` videos
+    // in a page to the following pages. A page only gets more of them when no other publisher
+    // has a video left, so that it stays full.
+    if max_per_publisher == 0 || page_size == 0 {
+        return candidates;
+    }
+    let mut ranking = Vec::with_capacity(candidates.len());
+    let mut remaining = candidates;
+    let mut size = match offset % page_size {
+        0 => page_size,
+        first_page_size => first_page_size,
+    };
+    while !remaining.is_empty() {
+        let mut per_publisher: HashMap<String, usize> = HashMap::new();
+        let mut page = Vec::new();
+        let mut deferred = Vec::new();
+        for candidate in remaining {
+            let count = per_publisher
+                .entry(candidate.publisher_id.clone())
+                .or_insert(0);
+            if page.len() < size && *count < max_per_publisher {
+                *count += 1;
+                page.push(candidate);
+            } else {
+                deferred.push(candidate);
+            }
+        }
+        let missing = (size - page.len()).min(deferred.len());
+        remaining = deferred.split_off(missing);
+        page.extend(deferred);
+        ranking.extend(page);
+        size = page_size;
+    }
+    ranking
+}
+
+#[derive(Default)]
+pub struct ExposureTracker {
+    // Number of times a video of each publisher was recommended since the server started
+    impressions: Mutex<HashMap<String, u64>>,
+}
+
+impl ExposureTracker {
+    pub fn record(&self, recommendations: &[VideoRecommendation]) {
+        let mut impressions = self.impressions.lock().unwrap();
+        for recommendation in recommendations {
+            *impressions
+                .entry(recommendation.publisher_id.clone())
+                .or_insert(0) += 1;
+        }
+    }
+
+    pub fn rerank(
+        &self,
+        mut candidates: Vec<VideoRecommendation>,
+        strength: f64,
+    ) -> Vec<VideoRecommendation> {
+        // Lower the score of the videos of the publishers that got the biggest share of the impressions,
+        // so that small publishers get their turn over time. The contributions are lowered in the same
+        // proportion, and the re-rankings that come after start from the lowered scores.
+        let impressions = self.impressions.lock().unwrap();
+        let total: u64 = impressions.values().sum();
+        if total == 0 {
+            return candidates;
+        }
+        for candidate in candidates.iter_mut() {
+            let share = impressions
+                .get(&candidate.publisher_id)
+                .copied()
+                .unwrap_or(0) as f64
+                / total as f64;
+            let factor = (1.0 - strength * share) as f32;
+            candidate.score *= factor;
+            if let Some(contributions) = candidate.contributions.as_mut() {
+                for contribution in contributions.values_mut() {
+                    *contribution *= factor;
+                }
+            }
+        }
+        candidates.sort_by(|a, b| b.score.total_cmp(&a.score).then_with(|| a.id.cmp(&b.id)));
+        candidates
+    }
+}
+
+pub struct FairnessReranker {
+    pub exposure: Arc<ExposureTracker>,
+    pub strength: f64,
+}
+
+impl Reranker for FairnessReranker {
+    fn rerank(
+        &self,
+        context: &RecommendationContext,
+        recommendations: Vec<VideoRecommendation>,
+    ) -> Vec<VideoRecommendation> {
+        if !context.options.fairness {
+            return recommendations;
+        }
+        self.exposure.rerank(recommendations, self.strength)
+    }
+}
+
+pub struct MmrReranker;
+
+impl Reranker for MmrReranker {
+    fn rerank(
+        &self,
+        context: &RecommendationContext,
+        mut recommendations: Vec<VideoRecommendation>,
+    ) -> Vec<VideoRecommendation> {
+        if context.options.diversity <= 0.0 {
+            return recommendations;
+        }
+        let rest = recommendations.split_off(RERANK_POOL_SIZE.min(recommendations.len()));
+        let size = recommendations.len();
+        let mut reranked = mmr(
+            recommendations,
+            &context.similarities,
+            context.options.diversity,
+            size,
+        );
+        reranked.extend(rest);
+        reranked
+    }
+}
+
+pub struct PublisherCapReranker;
+
+impl Reranker for PublisherCapReranker {
+    fn rerank(
+        &self,
+        context: &RecommendationContext,
+        recommendations: Vec<VideoRecommendation>,
+    ) -> Vec<VideoRecommendation> {
+        // The cap applies to each page of `limit` videos
+        cap_publishers(
+            recommendations,
+            context.options.max_per_publisher,
+            context.options.limit,
+            context.options.offset,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::context::tests::{ids, recommendation, similarities};
+
+    fn ranking(recommendations: &[VideoRecommendation]) -> Vec<String> {
+        ids(recommendations, |recommendation| &recommendation.id)
+    }
+
+    #[test]
+    fn mmr_moves_redundant_videos_down() {
+        let candidates = || {
+            vec![
+                recommendation("a", "publisher", 1.0),
+                recommendation("b", "publisher", 0.9),
+                recommendation("c", "publisher", 0.8),
+            ]
+        };
+        let edges = similarities(&[("a", "b", 0.9), ("a", "c", 0.1)]);
+
+        assert_eq!(
+            ranking(&mmr(candidates(), &edges, 0.0, 3)),
+            vec!["a", "b", "c"]
+        );
+        assert_eq!(
+            ranking(&mmr(candidates(), &edges, 0.5, 3)),
+            vec!["a", "c", "b"]
+        );
+        assert_eq!(ranking(&mmr(candidates(), &edges, 0.5, 2)), vec!["a", "c"]);
+    }
+
+    #[test]
+    fn cap_publishers_defers_videos_to_the_next_pages() {
+        let candidates = || {
+            vec![
+                recommendation("a1", "a", 1.0),
+                recommendation("a2", "a", 0.9),
+                recommendation("a3", "a", 0.8),
+                recommendation("b1", "b", 0.7),
+                recommendation("c1", "c", 0.6),
+                recommendation("b2", "b", 0.5),
+            ]
+        };
+
+        assert_eq!(
+            ranking(&cap_publishers(candidates(), 2, 3, 0)),
+            vec!["a1", "a2", "b1", "a3", "c1", "b2"]
+        );
+        // At most one video of a publisher in each page, unless only its videos are left
+        assert_eq!(
+            ranking(&cap_publishers(candidates(), 1, 3, 0)),
+            vec!["a1", "b1", "c1", "a2", "b2", "a3"]
+        );
+        // Pages start at the offset of the request
+        assert_eq!(
+            ranking(&cap_publishers(candidates(), 1, 3, 1)),
+            vec!["a1", "a2", "b1", "c1", "a3", "b2"]
+        );
+        assert_eq!(
+            ranking(&cap_publishers(candidates(), 0, 3, 0)),
+            ranking(&candidates())
+        );
+    }
+
+    #[test]
+    fn cap_publishers_fills_the_pages() {
+        let candidates = vec![
+            recommendation("a1", "a", 1.0),
+            recommendation("a2", "a", 0.9),
+            recommendation("a3", "a", 0.8),
+            recommendation("b1", "b", 0.7),
+        ];
+
+        assert_eq!(
+            ranking(&cap_publishers(candidates, 1, 2, 0)),
+            vec!["a1", "b1", "a2", "a3"]
+        );
+    }
+
+    #[test]
+    fn fairness_lowers_the_most_exposed_publishers() {
+        let exposure = ExposureTracker::default();
+        exposure.record(&[
+            recommendation("a1", "a", 1.0),
+            recommendation("a2", "a", 1.0),
+            recommendation("a3", "a", 1.0),
+            recommendation("b1", "b", 1.0),
+        ]);
+        let candidates = vec![
+            recommendation("a4", "a", 1.0),
+            recommendation("b2", "b", 0.8),
+        ];
+        let reranked = exposure.rerank(candidates, 0.5);
+
+        assert_eq!(ranking(&reranked), vec!["b2", "a4"]);
+        assert_eq!(reranked[0].score, 0.8 * 0.875);
+        assert_eq!(reranked[1].score, 0.625);
+    }
+}
@@ -0,0 +1,344 @@
+use std::{collections::HashMap, str::FromStr};
+
+use serde::Deserialize;
+
+use crate::{
+    config::Config,
+    context::RecommendationContext,
+    hybrid::Strategy,
+    models::{Interaction, InteractionKind, VideoMeta},
+    pipeline::Scorer,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Aggregation {
+    // Weighted mean of the similarities
+    Mean,
+    // Best weighted similarity
+    Max,
+    // Sum of the weighted similarities, rewards videos close to many videos of the history
+    Sum,
+    // Weighted mean of the `top_n` most similar videos of the history
+    TopN,
+}
+
+impl FromStr for Aggregation {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "mean" => Ok(Aggregation::Mean),
+            "max" => Ok(Aggregation::Max),
+            "sum" => Ok(Aggregation::Sum),
+            "top_n" => Ok(Aggregation::TopN),
+            _ => Err(format!("Unknown aggregation: {}", value)),
+        }
+    }
+}
+
+pub fn recency_decay(timestamp: Option<i64>, now: i64, half_life_days: f64) -> f64 {
+    // Halve the weight of an interaction every `half_life_days`,
+    // interactions without a timestamp are not decayed
+    match timestamp {
+        Some(timestamp) if half_life_days > 0.0 => {
+            let age_days = (now - timestamp).max(0) as f64 / 86400.0;
+            0.5_f64.powf(age_days / half_life_days)
+        }
+        _ => 1.0,
+    }
+}
+
+pub fn history_weights(history: &[Interaction], config: &Config, now: i64) -> HashMap<String, f64> {
+    // Weight of each video of the user history, depending on how the user interacted with it:
+    // a like, a watch scaled by the watched percentage and a bonus for each repeat view,
+    // each of them decayed by its age
+    let mut likes: HashMap<&str, f64> = HashMap::new();
+    let mut views: HashMap<&str, Vec<(f64, f64)>> = HashMap::new();
+    for interaction in history {
+        let decay = recency_decay(interaction.timestamp, now, config.history_half_life_days);
+        match interaction.kind {
+            InteractionKind::Like => {
+                likes.insert(&interaction.video_id, config.like_weight * decay);
+            }
+            InteractionKind::Watch => {
+                let percentage = interaction.watch_percentage.unwrap_or(1.0).clamp(0.0, 1.0);
+                views
+                    .entry(&interaction.video_id)
+                    .or_default()
+                    .push((config.watch_weight * percentage * decay, decay));
+            }
+        }
+    }
+
+    let mut weights: HashMap<String, f64> = likes
+        .into_iter()
+        .map(|(video_id, weight)| (video_id.to_string(), weight))
+        .collect();
+    for (video_id, mut watches) in views {
+        // The best view counts fully, the other ones only add the repeat view bonus
+        watches.sort_by(|a, b| b.0.total_cmp(&a.0));
+        let best_watch = watches[0].0;
+        let repeat_views: f64 = watches[1..]
+            .iter()
+            .map(|(_, decay)| config.repeat_view_weight * decay)
+            .sum();
+        *weights.entry(video_id.to_string()).or_insert(0.0) += best_watch + repeat_views;
+    }
+    weights
+}
+
+pub fn aggregate(
+    weighted_similarities: &[(f64, f64)],
+    aggregation: Aggregation,
+    top_n: usize,
+) -> Option<f64> {
+    // Combine the (weight, similarity) pairs of a candidate video, None if nothing can be combined
+    let total_weight: f64 = weighted_similarities.iter().map(|(weight, _)| weight).sum();
+    if weighted_similarities.is_empty() || total_weight <= 0.0 {
+        return None;
+    }
+
+    let weighted_sum = |pairs: &[(f64, f64)]| -> f64 {
+        pairs
+            .iter()
+            .map(|(weight, similarity)| weight * similarity)
+            .sum()
+    };
+
+    match aggregation {
+        Aggregation::Mean => Some(weighted_sum(weighted_similarities) / total_weight),
+        Aggregation::Max => weighted_similarities
+            .iter()
+            .map(|(weight, similarity)| weight * similarity)
+            .reduce(f64::max),
+        Aggregation::Sum => Some(weighted_sum(weighted_similarities)),
+        Aggregation::TopN => {
+            let mut pairs = weighted_similarities.to_vec();
+            pairs.sort_by(|a, b| b.1.total_cmp(&a.1));
+            pairs.truncate(top_n.max(1));
+            let total_weight: f64 = pairs.iter().map(|(weight, _)| weight).sum();
+            if total_weight <= 0.0 {
+                return None;
+            }
+            Some(weighted_sum(&pairs) / total_weight)
+        }
+    }
+}
+
+pub fn dismissal_penalty(similarities: impl IntoIterator<Item = f64>, dismiss_weight: f64) -> f64 {
+    // The closer a video is to a video the user dismissed, the more its score is lowered
+    similarities.into_iter().fold(0.0, f64::max) * dismiss_weight
+}
+
+pub struct ContentScorer;
+
+impl Scorer for ContentScorer {
+    fn strategy(&self) -> Strategy {
+        Strategy::Content
+    }
+
+    fn score(
+        &self,
+        context: &RecommendationContext,
+        candidates: &[VideoMeta],
+    ) -> HashMap<String, f64> {
+        // Similarity of each candidate with the videos of the history,
+        // weighted by the interaction the user had with them
+        let mut scores = HashMap::new();
+        for candidate in candidates {
+            let Some(similarities) = context.content_similarities.get(&candidate.video_id) else {
+                continue;
+            };
+            let weighted_similarities: Vec<(f64, f64)> = similarities
+                .iter()
+                .filter_map(|(watched_video_id, similarity)| {
+                    let weight = context.history.get(watched_video_id)?;
+                    Some((*weight, *similarity))
+                })
+                .collect();
+
+            // A video without any similarity to the watched ones can't be scored,
+            // skip it instead of producing a NaN score
+            if let Some(score) = aggregate(
+                &weighted_similarities,
+                context.options.aggregation,
+                context.options.top_n,
+            ) {
+                let penalty = dismissal_penalty(
+                    similarities
+                        .iter()
+                        .filter(|(video_id, _)| context.blocklist.videos.contains(video_id))
+                        .map(|(_, similarity)| *similarity),
+                    context.options.dismiss_weight,
+                );
+                scores.insert(candidate.video_id.clone(), score - penalty);
+            }
+        }
+        scores
+    }
+
+    fn explain(&self, context: &RecommendationContext, video_id: &str) -> Vec<(String, f64)> {
+        // Videos of the history with a description similar to the video
+        let Some(similarities) = context.content_similarities.get(video_id) else {
+            return Vec::new();
+        };
+        similarities
+            .iter()
+            .filter_map(|(watched_video_id, similarity)| {
+                let weight = context.history.get(watched_video_id)?;
+                Some((watched_video_id.clone(), weight * similarity))
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        config::tests::config,
+        context::tests::{context, interaction, like, options, similarities, video, watch},
+        source::content_similarities,
+    };
+
+    fn ids(video_ids: &[&str]) -> Vec<String> {
+        video_ids
+            .iter()
+            .map(|video_id| video_id.to_string())
+            .collect()
+    }
+
+    #[test]
+    fn aggregate_combines_the_weighted_similarities() {
+        let pairs = [(1.0, 0.9), (3.0, 0.5), (1.0, 0.1)];
+        let close = |actual: Option<f64>, expected: f64| {
+            assert!((actual.unwrap() - expected).abs() < 1e-9, "{:?}", actual)
+        };
+        close(aggregate(&pairs, Aggregation::Mean, 3), 2.5 / 5.0);
+        close(aggregate(&pairs, Aggregation::Max, 3), 1.5);
+        close(aggregate(&pairs, Aggregation::Sum, 3), 2.5);
+        // The 2 most similar videos of the history
+        close(aggregate(&pairs, Aggregation::TopN, 2), 2.4 / 4.0);
+    }
+
+    #[test]
+    fn aggregate_needs_a_weight() {
+        assert_eq!(aggregate(&[], Aggregation::Mean, 3), None);
+        assert_eq!(aggregate(&[(0.0, 0.8)], Aggregation::Mean, 3), None);
+        assert_eq!(aggregate(&[(0.0, 0.8)], Aggregation::TopN, 3), None);
+    }
+
+    #[test]
+    fn history_weights_add_up_likes_and_views() {
+        let mut half_watched = watch("user", "b");
+        half_watched.watch_percentage = Some(0.5);
+        let history = vec![
+            like("user", "a"),
+            watch("user", "a"),
+            half_watched,
+            watch("user", "c"),
+            watch("user", "c"),
+            watch("user", "c"),
+        ];
+        let weights = history_weights(&history, &config(), 0);
+
+        assert_eq!(weights.len(), 3);
+        assert_eq!(weights["a"], 2.0);
+        assert_eq!(weights["b"], 0.5);
+        // The best view and a bonus for each repeat view
+        assert_eq!(weights["c"], 1.5);
+    }
+
+    #[test]
+    fn history_weights_decay_with_age() {
+        let now = 1_000 * 86400;
+        let history = vec![
+            interaction("user", "a", InteractionKind::Like, Some(now)),
+            interaction("user", "b", InteractionKind::Like, Some(now - 90 * 86400)),
+            interaction("user", "c", InteractionKind::Like, None),
+        ];
+        let weights = history_weights(&history, &config(), now);
+
+        assert_eq!(weights["a"], 1.0);
+        assert!((weights["b"] - 0.5).abs() < 1e-9);
+        assert_eq!(weights["c"], 1.0);
+    }
+
+    #[test]
+    fn content_matches_the_per_pair_mean() {
+        // Before the single traversal, each candidate was scored with the mean similarity
+        // of the edges going to it from the videos of the history, looked up pair by pair
+        let videos: Vec<VideoMeta> = ["a", "b", "c", "d", "e", "f"]
+            .iter()
+            .map(|video_id| video(video_id, "publisher"))
+            .collect();
+        let edges = similarities(&[
+            ("a", "c", 0.8),
+            ("b", "c", 0.4),
+            ("a", "d", 0.5),
+            ("b", "e", 0.9),
+            ("c", "f", 0.7),
+        ]);
+        let history = ids(&["a", "b"]);
+        let not_seen = ids(&["c", "d", "e", "f"]);
+
+        let mut expected = HashMap::new();
+        for not_seen_video_id in &not_seen {
+            let mut total_similarity = 0.0;
+            let mut total_weight = 0.0;
+            for watched_video_id in &history {
+                if let Some(similarity) = edges
+                    .get(watched_video_id)
+                    .and_then(|neighbors| neighbors.get(not_seen_video_id))
+                {
+                    total_similarity += similarity;
+                    total_weight += 1.0;
+                }
+            }
+            if total_weight > 0.0 {
+                expected.insert(not_seen_video_id.clone(), total_similarity / total_weight);
+            }
+        }
+
+        let mut context = context(&videos, &[("a", 1.0), ("b", 1.0)], options());
+        context.content_similarities = content_similarities(&edges, &history, &not_seen);
+        let scores = ContentScorer.score(&context, &videos[2..]);
+
+        assert_eq!(scores.len(), expected.len());
+        for (video_id, score) in expected {
+            assert!((scores[&video_id] - score).abs() < 1e-9, "{}", video_id);
+        }
+    }
+
+    #[test]
+    fn content_is_weighted_and_lowered_near_dismissed_videos() {
+        let videos = vec![
+            video("a", "publisher"),
+            video("b", "publisher"),
+            video("c", "publisher"),
+            video("d", "publisher"),
+            video("x", "publisher"),
+        ];
+        let edges = similarities(&[
+            ("a", "c", 1.0),
+            ("b", "c", 0.5),
+            ("a", "d", 0.6),
+            ("x", "d", 0.5),
+        ]);
+        let mut options = options();
+        options.dismiss_weight = 1.0;
+        let mut context = context(&videos, &[("a", 1.0), ("b", 3.0)], options);
+        context.blocklist.videos.insert("x".to_string());
+        context.content_similarities =
+            content_similarities(&edges, &ids(&["a", "b", "x"]), &ids(&["c", "d"]));
+        let scores = ContentScorer.score(&context, &videos[2..4]);
+
+        assert!((scores["c"] - 2.5 / 4.0).abs() < 1e-9);
+        assert!((scores["d"] - (0.6 - 0.5)).abs() < 1e-9);
+        assert_eq!(
+            ContentScorer.explain(&context, "c"),
+            vec![("a".to_string(), 1.0), ("b".to_string(), 1.5)]
+        );
+    }
+}
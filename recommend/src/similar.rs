@@ -0,0 +1,69 @@
+use std::collections::HashMap;
+
+use axum::{
+    extract::{
+        rejection::{PathRejection, QueryRejection},
+        Path, Query, State,
+    },
+    Json,
+};
+
+use crate::{
+    error::{AppError, AppResult},
+    models::{get_limit, SimilarParams, VideoMeta, VideoRecommendation},
+    state::AppState,
+};
+
+pub fn rank_similar_videos(
+    video_id: &str,
+    videos: &[VideoMeta],
+    similarities: Option<&HashMap<String, f64>>,
+    min_score: f64,
+) -> AppResult<Vec<VideoRecommendation>> {
+    // Videos linked to the given one, most similar first
+    if !videos.iter().any(|video| video.video_id == video_id) {
+        return Err(AppError::UnknownVideo(video_id.to_string()));
+    }
+    let Some(similarities) = similarities else {
+        return Ok(Vec::new());
+    };
+
+    let mut ranking: Vec<VideoRecommendation> = videos
+        .iter()
+        .filter(|video| video.video_id != video_id)
+        .filter_map(|video| {
+            let similarity = *similarities.get(&video.video_id)?;
+            (similarity >= min_score).then(|| VideoRecommendation {
+                id: video.video_id.clone(),
+                title: video.title.clone(),
+                publisher_id: video.publisher_id.clone(),
+                score: similarity as f32,
+                contributions: None,
+                reasons: None,
+            })
+        })
+        .collect();
+    ranking.sort_by(|a, b| b.score.total_cmp(&a.score).then_with(|| a.id.cmp(&b.id)));
+    Ok(ranking)
+}
+
+pub async fn get_similar_videos(
+    State(state): State<AppState>,
+    path: Result<Path<String>, PathRejection>,
+    query: Result<Query<SimilarParams>, QueryRejection>,
+) -> AppResult<Json<Vec<VideoRecommendation>>> {
+    let Path(video_id) = path?;
+    let Query(query_params) = query?;
+    let limit = get_limit(query_params.limit)?;
+    let min_score = query_params.min_score.unwrap_or(0.0);
+
+    let data = state.data();
+    let similarities = data.get_similar_videos(&video_id).await?;
+    let ranking = rank_similar_videos(
+        &video_id,
+        data.get_videos().await?,
+        Some(&similarities),
+        min_score,
+    )?;
+    Ok(Json(ranking.into_iter().take(limit).collect()))
+}
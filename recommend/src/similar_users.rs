@@ -0,0 +1,173 @@
+use std::collections::{HashMap, HashSet};
+
+use axum::{
+    extract::{
+        rejection::{PathRejection, QueryRejection},
+        Path, Query, State,
+    },
+    Json,
+};
+use serde::Serialize;
+use uuid::Uuid;
+
+use crate::{
+    config::Config,
+    error::AppResult,
+    models::{get_limit, Interaction, InteractionKind, SimilarParams},
+    popularity::now,
+    scoring::history_weights,
+    state::AppState,
+};
+
+#[derive(Debug, Serialize)]
+pub struct SimilarUser {
+    pub user_id: String,
+    // Cosine similarity of the weighted histories
+    pub score: f64,
+    // Videos both users liked or watched
+    pub common_videos: usize,
+    pub common_likes: usize,
+    pub common_watches: usize,
+}
+
+fn videos_by_kind(history: &[Interaction], kind: InteractionKind) -> HashSet<&str> {
+    history
+        .iter()
+        .filter(|interaction| interaction.kind == kind)
+        .map(|interaction| interaction.video_id.as_str())
+        .collect()
+}
+
+fn norm(weights: &HashMap<String, f64>) -> f64 {
+    weights
+        .values()
+        .map(|weight| weight * weight)
+        .sum::<f64>()
+        .sqrt()
+}
+
+pub fn rank_similar_users(
+    user_id: &str,
+    history: &[Interaction],
+    interactions: &[Interaction],
+    config: &Config,
+    min_score: f64,
+) -> Vec<SimilarUser> {
+    // Compare the history of the user with every other one, each video weighted
+    // like for the recommendations, and count the videos they have in common. The history of
+    // the user comes with its seed likes, the ones of the others without.
+    let mut histories: HashMap<&str, Vec<Interaction>> = HashMap::new();
+    for interaction in interactions {
+        if interaction.user_id != user_id {
+            histories
+                .entry(interaction.user_id.as_str())
+                .or_default()
+                .push(interaction.clone());
+        }
+    }
+    if history.is_empty() {
+        return Vec::new();
+    }
+    let now = now();
+    let weights = history_weights(history, config, now);
+    let likes = videos_by_kind(history, InteractionKind::Like);
+    let watches = videos_by_kind(history, InteractionKind::Watch);
+
+    let mut similar_users: Vec<SimilarUser> = histories
+        .iter()
+        .filter_map(|(other_user_id, other_history)| {
+            let other_weights = history_weights(other_history, config, now);
+            let dot: f64 = weights
+                .iter()
+                .filter_map(|(video_id, weight)| Some(weight * other_weights.get(video_id)?))
+                .sum();
+            let norms = norm(&weights) * norm(&other_weights);
+            if dot <= 0.0 || norms <= 0.0 {
+                return None;
+            }
+            let score = dot / norms;
+            (score >= min_score).then(|| SimilarUser {
+                user_id: other_user_id.to_string(),
+                score,
+                common_videos: weights
+                    .keys()
+                    .filter(|video_id| other_weights.contains_key(*video_id))
+                    .count(),
+                common_likes: likes
+                    .intersection(&videos_by_kind(other_history, InteractionKind::Like))
+                    .count(),
+                common_watches: watches
+                    .intersection(&videos_by_kind(other_history, InteractionKind::Watch))
+                    .count(),
+            })
+        })
+        .collect();
+    similar_users.sort_by(|a, b| {
+        b.score
+            .total_cmp(&a.score)
+            .then_with(|| b.common_videos.cmp(&a.common_videos))
+            .then_with(|| a.user_id.cmp(&b.user_id))
+    });
+    similar_users
+}
+
+pub async fn get_similar_users(
+    State(state): State<AppState>,
+    path: Result<Path<Uuid>, PathRejection>,
+    query: Result<Query<SimilarParams>, QueryRejection>,
+) -> AppResult<Json<Vec<SimilarUser>>> {
+    let Path(user_id) = path?;
+    let Query(query_params) = query?;
+    let limit = get_limit(query_params.limit)?;
+    let min_score = query_params.min_score.unwrap_or(0.0);
+
+    let data = state.data();
+    data.check_user(user_id).await?;
+    let similar_users = rank_similar_users(
+        &user_id.to_string(),
+        &data.get_user_history(user_id).await?,
+        data.get_interactions().await?,
+        &state.config,
+        min_score,
+    );
+    Ok(Json(similar_users.into_iter().take(limit).collect()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        config::tests::config,
+        context::tests::{ids, like, watch},
+    };
+
+    #[test]
+    fn similar_users_share_the_most_videos() {
+        let history = vec![like("user", "a"), watch("user", "b")];
+        let interactions = vec![
+            like("user", "a"),
+            watch("user", "b"),
+            like("twin", "a"),
+            watch("twin", "b"),
+            like("close", "a"),
+            like("close", "c"),
+            watch("stranger", "d"),
+        ];
+        let similar_users = rank_similar_users("user", &history, &interactions, &config(), 0.0);
+
+        // The user isn't similar to themselves, `stranger` has nothing in common
+        assert_eq!(
+            ids(&similar_users, |similar_user| &similar_user.user_id),
+            vec!["twin", "close"]
+        );
+        assert!((similar_users[0].score - 1.0).abs() < 1e-9);
+        assert_eq!(similar_users[0].common_videos, 2);
+        assert_eq!(similar_users[0].common_likes, 1);
+        assert_eq!(similar_users[0].common_watches, 1);
+        assert!((similar_users[1].score - 0.5).abs() < 1e-9);
+
+        let similar_users = rank_similar_users("user", &history, &interactions, &config(), 0.6);
+        assert_eq!(similar_users.len(), 1);
+        assert!(rank_similar_users("user", &[], &interactions, &config(), 0.0).is_empty());
+    }
+}
@@ -0,0 +1,221 @@
+use std::{
+    collections::{HashMap, HashSet},
+    path::{Path, PathBuf},
+    sync::{Arc, RwLock},
+    time::{Duration, SystemTime},
+};
+
+use axum::{extract::State, Json};
+use serde::Serialize;
+use tokio::sync::Notify;
+use uuid::Uuid;
+
+use crate::{
+    error::{AppError, AppResult},
+    graph::Graph,
+    models::{Dismissal, Interaction, PartialView},
+    popularity::now,
+    source::Catalog,
+    state::AppState,
+};
+
+// Everything the recommendations are computed from, loaded from the graph in a few queries
+pub struct Snapshot {
+    pub catalog: Catalog,
+    pub users: HashSet<String>,
+    // Interactions of each user, indexed by user id
+    histories: HashMap<String, Vec<Interaction>>,
+    // Videos dismissed by each user, indexed by user id
+    dismissals: HashMap<String, Vec<Dismissal>>,
+    // Videos started and not finished by each user, indexed by user id
+    partial_views: HashMap<String, Vec<PartialView>>,
+    pub loaded_at: i64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SnapshotSummary {
+    pub videos: usize,
+    pub users: usize,
+    pub interactions: usize,
+    pub similarities: usize,
+    pub publishers: usize,
+    pub loaded_at: i64,
+}
+
+impl Snapshot {
+    pub async fn load(graph: &Graph) -> AppResult<Snapshot> {
+        // The catalog comes with the item similarities and the popularity, computed once here
+        // instead of on each request
+        let interactions = graph.get_interactions().await?;
+        let catalog = Catalog::load(graph, &interactions).await?;
+        let users = graph.get_user_ids().await?;
+        let mut dismissals: HashMap<String, Vec<Dismissal>> = HashMap::new();
+        for dismissal in graph.get_dismissals().await? {
+            dismissals
+                .entry(dismissal.user_id.clone())
+                .or_default()
+                .push(dismissal);
+        }
+        let mut partial_views: HashMap<String, Vec<PartialView>> = HashMap::new();
+        for partial_view in graph.get_partial_views().await? {
+            partial_views
+                .entry(partial_view.user_id.clone())
+                .or_default()
+                .push(partial_view);
+        }
+
+        let mut histories: HashMap<String, Vec<Interaction>> = HashMap::new();
+        // Histories keep the seed likes, left out of the catalog
+        for interaction in interactions {
+            histories
+                .entry(interaction.user_id.clone())
+                .or_default()
+                .push(interaction);
+        }
+
+        Ok(Snapshot {
+            catalog,
+            users,
+            histories,
+            dismissals,
+            partial_views,
+            loaded_at: now(),
+        })
+    }
+
+    pub fn summary(&self) -> SnapshotSummary {
+        SnapshotSummary {
+            videos: self.catalog.videos.get().map_or(0, |videos| videos.len()),
+            users: self.users.len(),
+            interactions: self
+                .catalog
+                .interactions
+                .get()
+                .map_or(0, |interactions| interactions.len()),
+            similarities: self.catalog.similarities.get().map_or(0, |similarities| {
+                similarities.values().map(|edges| edges.len()).sum()
+            }),
+            publishers: self
+                .catalog
+                .publishers
+                .get()
+                .map_or(0, |publishers| publishers.len()),
+            loaded_at: self.loaded_at,
+        }
+    }
+
+    pub fn user_exists(&self, user_id: Uuid) -> bool {
+        self.users.contains(&user_id.to_string())
+    }
+
+    pub fn get_user_history(&self, user_id: Uuid) -> Vec<Interaction> {
+        self.histories
+            .get(&user_id.to_string())
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    pub fn get_user_dismissals(&self, user_id: Uuid) -> Vec<Dismissal> {
+        self.dismissals
+            .get(&user_id.to_string())
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    pub fn get_dismissals(&self) -> Vec<Dismissal> {
+        self.dismissals.values().flatten().cloned().collect()
+    }
+
+    pub fn get_user_partial_views(&self, user_id: Uuid) -> Vec<PartialView> {
+        self.partial_views
+            .get(&user_id.to_string())
+            .cloned()
+            .unwrap_or_default()
+    }
+}
+
+// Holds the current snapshot, a new one is swapped in once fully loaded so that
+// requests never see a partial snapshot
+pub struct SnapshotStore {
+    graph: Graph,
+    current: RwLock<Arc<Snapshot>>,
+    // Woken up when the graph was changed by the service itself
+    reload_requested: Notify,
+}
+
+impl SnapshotStore {
+    pub async fn load(graph: Graph) -> AppResult<SnapshotStore> {
+        let snapshot = Snapshot::load(&graph).await?;
+        println!("Snapshot loaded: {:?}", snapshot.summary());
+        Ok(SnapshotStore {
+            graph,
+            current: RwLock::new(Arc::new(snapshot)),
+            reload_requested: Notify::new(),
+        })
+    }
+
+    pub fn get(&self) -> Arc<Snapshot> {
+        self.current.read().unwrap().clone()
+    }
+
+    pub fn request_reload(&self) {
+        self.reload_requested.notify_one();
+    }
+
+    pub async fn reload(&self) -> AppResult<Arc<Snapshot>> {
+        // On failure the previous snapshot keeps being served
+        let snapshot = Arc::new(Snapshot::load(&self.graph).await?);
+        println!("Snapshot reloaded: {:?}", snapshot.summary());
+        *self.current.write().unwrap() = snapshot.clone();
+        Ok(snapshot)
+    }
+}
+
+fn modified_at(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path)
+        .and_then(|meta| meta.modified())
+        .ok()
+}
+
+pub fn watch(store: Arc<SnapshotStore>, path: PathBuf, interval: Duration) {
+    // Reload the snapshot whenever the modification time of the file changes
+    tokio::spawn(async move {
+        let mut last_modified = modified_at(&path);
+        loop {
+            tokio::time::sleep(interval).await;
+            let modified = modified_at(&path);
+            if modified == last_modified {
+                continue;
+            }
+            last_modified = modified;
+            println!("{} changed, reloading the snapshot", path.display());
+            if let Err(err) = store.reload().await {
+                println!("Failed to reload the snapshot: {}", err);
+            }
+        }
+    });
+}
+
+pub fn reload_on_request(store: Arc<SnapshotStore>, delay: Duration) {
+    // Reload the snapshot a little after a change was requested, so that the requests
+    // made in the meantime are loaded together
+    tokio::spawn(async move {
+        loop {
+            store.reload_requested.notified().await;
+            tokio::time::sleep(delay).await;
+            if let Err(err) = store.reload().await {
+                println!("Failed to reload the snapshot: {}", err);
+            }
+        }
+    });
+}
+
+pub async fn reload_snapshot(State(state): State<AppState>) -> AppResult<Json<SnapshotSummary>> {
+    let Some(store) = state.snapshot else {
+        return Err(AppError::BadRequest(
+            "The snapshot is disabled, recommendations are computed from the graph".to_string(),
+        ));
+    };
+    let snapshot = store.reload().await?;
+    Ok(Json(snapshot.summary()))
+}
@@ -0,0 +1,260 @@
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Arc,
+};
+
+use tokio::sync::OnceCell;
+use uuid::Uuid;
+
+use crate::{
+    collaborative::item_similarities,
+    error::{AppError, AppResult},
+    graph::Graph,
+    models::{Dismissal, Interaction, PartialView, Similarities, VideoMeta},
+    popularity::popularity_scores,
+    snapshot::Snapshot,
+};
+
+// Data shared by every user, along with what is derived from it. The snapshot loads all of it
+// up front, without a snapshot each part is loaded from the graph the first time it is needed.
+#[derive(Default)]
+pub struct Catalog {
+    pub videos: OnceCell<Vec<VideoMeta>>,
+    // Every like and watch, but the seed likes: they only count for the user who picked them
+    pub interactions: OnceCell<Vec<Interaction>>,
+    // `similar_to` edges, indexed by the video they start from
+    pub similarities: OnceCell<Similarities>,
+    // Videos of each publisher, indexed by publisher id
+    pub publishers: OnceCell<HashMap<String, Vec<String>>>,
+    // Similarities between the videos consumed by the same users
+    pub item_similarities: OnceCell<Similarities>,
+    // Number of likes and completed watches of each video
+    pub popularity: OnceCell<HashMap<String, f64>>,
+}
+
+impl Catalog {
+    pub async fn load(graph: &Graph, interactions: &[Interaction]) -> AppResult<Catalog> {
+        let interactions = shared_interactions(interactions);
+        let item_similarities = item_similarities(&interactions);
+        let popularity = popularity_scores(&interactions);
+        Ok(Catalog {
+            videos: OnceCell::new_with(Some(graph.get_videos().await?)),
+            interactions: OnceCell::new_with(Some(interactions)),
+            similarities: OnceCell::new_with(Some(graph.get_all_similarities().await?)),
+            publishers: OnceCell::new_with(Some(graph.get_publishers().await?)),
+            item_similarities: OnceCell::new_with(Some(item_similarities)),
+            popularity: OnceCell::new_with(Some(popularity)),
+        })
+    }
+}
+
+pub fn shared_interactions(interactions: &[Interaction]) -> Vec<Interaction> {
+    // The seed likes of a user are left out of what is computed for everyone,
+    // such as the popularity, the trending videos and the collaborative scores
+    interactions
+        .iter()
+        .filter(|interaction| !interaction.seed)
+        .cloned()
+        .collect()
+}
+
+pub fn content_similarities(
+    similarities: &Similarities,
+    source_video_ids: &[String],
+    not_seen_video_ids: &[String],
+) -> HashMap<String, Vec<(String, f64)>> {
+    // Same as the graph query, from the edges in memory
+    not_seen_video_ids
+        .iter()
+        .map(|not_seen_video_id| {
+            let video_similarities = source_video_ids
+                .iter()
+                .filter_map(|source_video_id| {
+                    let similarity = similarities.get(source_video_id)?.get(not_seen_video_id)?;
+                    Some((source_video_id.to_owned(), *similarity))
+                })
+                .collect();
+            (not_seen_video_id.to_owned(), video_similarities)
+        })
+        .collect()
+}
+
+pub fn similarities_between(similarities: &Similarities, video_ids: &[String]) -> Similarities {
+    let video_ids: HashSet<&str> = video_ids.iter().map(|id| id.as_str()).collect();
+    similarities
+        .iter()
+        .filter(|(from, _)| video_ids.contains(from.as_str()))
+        .map(|(from, edges)| {
+            let edges = edges
+                .iter()
+                .filter(|(to, _)| video_ids.contains(to.as_str()))
+                .map(|(to, similarity)| (to.clone(), *similarity))
+                .collect();
+            (from.clone(), edges)
+        })
+        .collect()
+}
+
+// Where the handlers read from: the snapshot when there is one, otherwise the graph.
+// Without a snapshot, the catalog is kept for as long as the source, e.g. a request or a batch.
+pub struct DataSource {
+    graph: Graph,
+    snapshot: Option<Arc<Snapshot>>,
+    catalog: Catalog,
+}
+
+impl DataSource {
+    pub fn new(graph: Graph, snapshot: Option<Arc<Snapshot>>) -> DataSource {
+        DataSource {
+            graph,
+            snapshot,
+            catalog: Catalog::default(),
+        }
+    }
+
+    fn catalog(&self) -> &Catalog {
+        match &self.snapshot {
+            Some(snapshot) => &snapshot.catalog,
+            None => &self.catalog,
+        }
+    }
+
+    pub async fn check_user(&self, user_id: Uuid) -> AppResult<()> {
+        let known_user = match &self.snapshot {
+            Some(snapshot) => snapshot.user_exists(user_id),
+            None => self.graph.user_exists(user_id).await?,
+        };
+        if !known_user {
+            return Err(AppError::UnknownUser(user_id));
+        }
+        Ok(())
+    }
+
+    pub async fn get_user_history(&self, user_id: Uuid) -> AppResult<Vec<Interaction>> {
+        match &self.snapshot {
+            Some(snapshot) => Ok(snapshot.get_user_history(user_id)),
+            None => self.graph.get_user_history(user_id).await,
+        }
+    }
+
+    pub async fn get_user_dismissals(&self, user_id: Uuid) -> AppResult<Vec<Dismissal>> {
+        match &self.snapshot {
+            Some(snapshot) => Ok(snapshot.get_user_dismissals(user_id)),
+            None => self.graph.get_user_dismissals(user_id).await,
+        }
+    }
+
+    pub async fn get_user_partial_views(&self, user_id: Uuid) -> AppResult<Vec<PartialView>> {
+        match &self.snapshot {
+            Some(snapshot) => Ok(snapshot.get_user_partial_views(user_id)),
+            None => self.graph.get_user_partial_views(user_id).await,
+        }
+    }
+
+    pub async fn get_dismissals(&self) -> AppResult<Vec<Dismissal>> {
+        match &self.snapshot {
+            Some(snapshot) => Ok(snapshot.get_dismissals()),
+            None => self.graph.get_dismissals().await,
+        }
+    }
+
+    pub async fn get_videos(&self) -> AppResult<&[VideoMeta]> {
+        let videos = self
+            .catalog()
+            .videos
+            .get_or_try_init(|| self.graph.get_videos())
+            .await?;
+        Ok(videos)
+    }
+
+    pub async fn get_video(&self, video_id: &str) -> AppResult<&VideoMeta> {
+        self.get_videos()
+            .await?
+            .iter()
+            .find(|video| video.video_id == video_id)
+            .ok_or_else(|| AppError::UnknownVideo(video_id.to_string()))
+    }
+
+    pub async fn get_interactions(&self) -> AppResult<&[Interaction]> {
+        let interactions = self
+            .catalog()
+            .interactions
+            .get_or_try_init(|| async {
+                let interactions = self.graph.get_interactions().await?;
+                AppResult::Ok(shared_interactions(&interactions))
+            })
+            .await?;
+        Ok(interactions)
+    }
+
+    pub async fn get_similarities(&self) -> AppResult<&Similarities> {
+        self.catalog()
+            .similarities
+            .get_or_try_init(|| self.graph.get_all_similarities())
+            .await
+    }
+
+    pub async fn get_publishers(&self) -> AppResult<&HashMap<String, Vec<String>>> {
+        self.catalog()
+            .publishers
+            .get_or_try_init(|| self.graph.get_publishers())
+            .await
+    }
+
+    pub async fn get_item_similarities(&self) -> AppResult<&Similarities> {
+        self.catalog()
+            .item_similarities
+            .get_or_try_init(|| async { Ok(item_similarities(self.get_interactions().await?)) })
+            .await
+    }
+
+    pub async fn get_popularity(&self) -> AppResult<&HashMap<String, f64>> {
+        self.catalog()
+            .popularity
+            .get_or_try_init(|| async { Ok(popularity_scores(self.get_interactions().await?)) })
+            .await
+    }
+
+    pub async fn get_similar_videos(&self, video_id: &str) -> AppResult<HashMap<String, f64>> {
+        // `similar_to` edges going from the video
+        match &self.snapshot {
+            Some(_) => Ok(self
+                .get_similarities()
+                .await?
+                .get(video_id)
+                .cloned()
+                .unwrap_or_default()),
+            None => self.graph.get_similar_videos(video_id).await,
+        }
+    }
+
+    pub async fn get_content_similarities(
+        &self,
+        source_video_ids: &[String],
+        not_seen_video_ids: &[String],
+    ) -> AppResult<HashMap<String, Vec<(String, f64)>>> {
+        // For each video not seen, its similarity to the given videos of the history and the dismissed ones
+        match &self.snapshot {
+            Some(_) => Ok(content_similarities(
+                self.get_similarities().await?,
+                source_video_ids,
+                not_seen_video_ids,
+            )),
+            None => {
+                self.graph
+                    .get_content_similarities(source_video_ids, not_seen_video_ids)
+                    .await
+            }
+        }
+    }
+
+    pub async fn get_similarities_between(&self, video_ids: &[String]) -> AppResult<Similarities> {
+        match &self.snapshot {
+            Some(_) => Ok(similarities_between(
+                self.get_similarities().await?,
+                video_ids,
+            )),
+            None => self.graph.get_similarities_between(video_ids).await,
+        }
+    }
+}
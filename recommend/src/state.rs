@@ -0,0 +1,53 @@
+use std::sync::Arc;
+
+use crate::{
+    config::Config,
+    graph::Graph,
+    pipeline::Pipeline,
+    rerank::ExposureTracker,
+    snapshot::{Snapshot, SnapshotStore},
+    source::DataSource,
+};
+
+#[derive(Clone)]
+pub struct AppState {
+    pub config: Arc<Config>,
+    pub graph: Graph,
+    pub exposure: Arc<ExposureTracker>,
+    pub pipeline: Arc<Pipeline>,
+    // None when the recommendations are computed from the graph on each request
+    pub snapshot: Option<Arc<SnapshotStore>>,
+}
+
+impl AppState {
+    pub async fn new(config: Config) -> AppState {
+        let graph = Graph::connect(&config).await;
+        let exposure = Arc::new(ExposureTracker::default());
+        let pipeline = Arc::new(Pipeline::from_config(&config, exposure.clone()));
+        let snapshot = if config.snapshot {
+            Some(Arc::new(
+                SnapshotStore::load(graph.clone())
+                    .await
+                    .expect("Failed to load the snapshot"),
+            ))
+        } else {
+            None
+        };
+        AppState {
+            config: Arc::new(config),
+            graph,
+            exposure,
+            pipeline,
+            snapshot,
+        }
+    }
+
+    pub fn snapshot(&self) -> Option<Arc<Snapshot>> {
+        self.snapshot.as_ref().map(|store| store.get())
+    }
+
+    pub fn data(&self) -> DataSource {
+        // Served from the current snapshot when there is one, otherwise from the graph
+        DataSource::new(self.graph.clone(), self.snapshot())
+    }
+}